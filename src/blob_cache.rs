@@ -0,0 +1,101 @@
+use git2::Oid;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+/// Hit and miss counters of a `CodeRepository`'s blob cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+/// Least-recently-used cache of decoded blob contents keyed by blob id.
+pub(crate) struct BlobCache {
+    capacity: usize,
+    contents: HashMap<Oid, Arc<String>>,
+    recency: VecDeque<Oid>,
+    stats: CacheStats,
+}
+
+impl BlobCache {
+    pub fn new(capacity: usize) -> BlobCache {
+        BlobCache{
+            capacity,
+            contents: HashMap::new(),
+            recency: VecDeque::new(),
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn get(&mut self, id: Oid) -> Option<Arc<String>> {
+        match self.contents.get(&id) {
+            Some(content) => {
+                self.stats.hits += 1;
+                if let Some(position) = self.recency.iter().position(|o| *o == id) {
+                    self.recency.remove(position);
+                }
+                self.recency.push_back(id);
+                Some(content.clone())
+            },
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn insert(&mut self, id: Oid, content: Arc<String>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.contents.insert(id, content).is_none() {
+            self.recency.push_back(id);
+        }
+        while self.recency.len() > self.capacity {
+            if let Some(oldest) = self.recency.pop_front() {
+                self.contents.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn oid(n: u8) -> Oid {
+        Oid::from_bytes(&[n; 20]).unwrap()
+    }
+
+    #[test]
+    fn cached_content_is_a_hit() {
+        let mut cache = BlobCache::new(2);
+        assert!(cache.get(oid(1)).is_none());
+        cache.insert(oid(1), Arc::new("one".into()));
+        assert_eq!(cache.get(oid(1)).unwrap().as_str(), "one");
+        assert_eq!(cache.stats(), CacheStats{hits: 1, misses: 1});
+    }
+
+    #[test]
+    fn least_recently_used_entry_is_evicted() {
+        let mut cache = BlobCache::new(2);
+        cache.insert(oid(1), Arc::new("one".into()));
+        cache.insert(oid(2), Arc::new("two".into()));
+        cache.get(oid(1));
+        cache.insert(oid(3), Arc::new("three".into()));
+        assert!(cache.get(oid(2)).is_none());
+        assert!(cache.get(oid(1)).is_some());
+        assert!(cache.get(oid(3)).is_some());
+    }
+
+    #[test]
+    fn zero_capacity_caches_nothing() {
+        let mut cache = BlobCache::new(0);
+        cache.insert(oid(1), Arc::new("one".into()));
+        assert!(cache.get(oid(1)).is_none());
+    }
+}
@@ -1,37 +1,79 @@
+use std::fmt;
 use std::ops::Range;
 use std::path::{PathBuf, Path};
 
+/// How the commit changed the file of a `ChangeSet`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChangeStatus {
+    Added,
+    Deleted,
+    #[default]
+    Modified,
+    /// Moved from `old_path` to the `filename` of the change set. Its `code` and `lines` are
+    /// those of the file at `old_path` before the move. Only reported if renames are detected,
+    /// see `ExtractOptions::detect_renames`.
+    Renamed { old_path: PathBuf },
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ChangeSet {
     pub filename: PathBuf,
     pub code: Vec<String>,
-    pub lines: Vec<usize>
+    pub lines: Vec<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub status: ChangeStatus,
 }
 
 impl ChangeSet {
+    /// Split `code` into lines at every `"\n"`. A `"\r"` before it stays part of the line,
+    /// so the text of CRLF files is reproduced byte for byte.
     pub fn new<P: AsRef<Path> + ?Sized>(filename: &P, code: &str) -> ChangeSet {
+        let lines = code.split_inclusive('\n').map(|l| l.strip_suffix('\n').unwrap_or(l).into());
+        ChangeSet::from_lines(filename, lines.collect())
+    }
+
+    /// Like `new` with the code already split into lines, which are kept as they are.
+    pub fn from_lines<P: AsRef<Path> + ?Sized>(filename: &P, lines: Vec<String>) -> ChangeSet {
         ChangeSet{
             filename: PathBuf::from(filename.as_ref()),
-            code: code.lines().map(|l| l.into()).collect(), 
-            lines: vec![]
+            code: lines,
+            lines: vec![],
+            status: ChangeStatus::default(),
         }
     }
 
+    pub fn with_status(self, status: ChangeStatus) -> ChangeSet {
+        ChangeSet{status, ..self}
+    }
+
+    /// Add a changed line, keeping `lines` sorted and free of duplicates.
     pub fn add_line(&mut self, line_number: usize) {
-        self.lines.push(line_number);
+        if let Err(position) = self.lines.binary_search(&line_number) {
+            self.lines.insert(position, line_number);
+        }
     }
 
+    /// Consecutive changed lines coalesced into ranges, in ascending order regardless of the
+    /// order the lines were added in.
     pub fn ranges(&self) -> Vec<Range<usize>> {
         let mut ranges: Vec<Range<usize>> = vec![];    
         if self.lines.is_empty() {
             return ranges
         }
 
-		let start = *self.lines.get(0).unwrap();
+		let mut lines = self.lines.clone();
+		lines.sort_unstable();
+		lines.dedup();
+
+		let mut start = *lines.first().unwrap();
 
 		let mut next = start;
-		for end in self.lines.iter().skip(1) {
+		for end in lines.iter().skip(1) {
 			if next + 1 != *end {
 				ranges.push(start..next+1);
+				start = *end;
 			}
 			next = *end;
 		}
@@ -40,8 +82,102 @@ impl ChangeSet {
         ranges
     }
 
+    /// Like `ranges`, but ranges separated by at most `max_gap` unchanged lines are merged, so
+    /// `max_gap = 0` yields the same ranges.
+    pub fn ranges_with_gap(&self, max_gap: usize) -> Vec<Range<usize>> {
+        let mut merged: Vec<Range<usize>> = vec![];
+        for range in self.ranges() {
+            match merged.last_mut() {
+                Some(last) if range.start - last.end <= max_gap => last.end = range.end,
+                _ => merged.push(range),
+            }
+        }
+        merged
+    }
+
+    /// The lines of code within `range`, ignoring the part of it past the end of the code.
+    fn code_in(&self, range: Range<usize>) -> &[String] {
+        let end = range.end.min(self.code.len());
+        let start = range.start.min(end);
+        &self.code[start..end]
+    }
+
+    /// The lines of code within `range` as they are in the source, without the line break
+    /// of the last one.
+    fn text_in(&self, range: Range<usize>) -> String {
+        let text = self.code_in(range).join("\n");
+        text.strip_suffix('\r').map(String::from).unwrap_or(text)
+    }
+
+    /// The code of every range with the line breaks of the source, `"\r\n"` in a CRLF file,
+    /// but none after its last line. Lines past the end of the code are left out, and so are
+    /// ranges containing no other line.
     pub fn text_ranges(&self) -> Vec<String> {
-        self.ranges().iter().map(|r| self.code[r.clone()].join("") ).collect()
+        self.ranges().into_iter()
+            .filter(|r| !self.code_in(r.clone()).is_empty())
+            .map(|r| self.text_in(r))
+            .collect()
+    }
+
+    /// Like `text_ranges` with every range widened by `before` and `after` lines within the
+    /// code. Ranges touching or overlapping after widening are merged into one snippet, and
+    /// ranges entirely past the end of the code are left out.
+    pub fn text_ranges_with_context(&self, before: usize, after: usize) -> Vec<String> {
+        let mut widened: Vec<Range<usize>> = vec![];
+        for range in self.ranges() {
+            let start = range.start.saturating_sub(before);
+            let end = (range.end + after).min(self.code.len());
+            match widened.last_mut() {
+                Some(last) if start <= last.end => last.end = last.end.max(end),
+                _ => widened.push(start..end),
+            }
+        }
+        widened.into_iter()
+            .filter(|r| !self.code_in(r.clone()).is_empty())
+            .map(|r| self.text_in(r))
+            .collect()
+    }
+
+    /// The smallest range covering every changed line.
+    pub fn bounding_range(&self) -> Option<Range<usize>> {
+        let start = *self.lines.iter().min()?;
+        let end = *self.lines.iter().max()?;
+        Some(start..end+1)
+    }
+
+    /// The code of `bounding_range`, one line per changed or intermediate line.
+    pub fn bounding_text(&self) -> Option<String> {
+        Some(self.text_in(self.bounding_range()?))
+    }
+}
+
+/// Change sets are equal if they cover the same lines of the same code, in any order, and the
+/// file was changed the same way.
+impl PartialEq for ChangeSet {
+    fn eq(&self, other: &ChangeSet) -> bool {
+        let sorted = |lines: &[usize]| {
+            let mut lines = lines.to_vec();
+            lines.sort_unstable();
+            lines
+        };
+        self.filename == other.filename && self.code == other.code && sorted(&self.lines) == sorted(&other.lines)
+            && self.status == other.status
+    }
+}
+
+impl Eq for ChangeSet {}
+
+/// The filename followed by every range as `@@ start..end @@` and its lines of code.
+impl fmt::Display for ChangeSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.filename.display())?;
+        for range in self.ranges() {
+            writeln!(f, "@@ {}..{} @@", range.start, range.end)?;
+            for line in self.code_in(range) {
+                writeln!(f, "{}", line)?;
+            }
+        }
+        Ok(())
     }
 }
 
@@ -56,7 +192,7 @@ mod tests {
         let mut cs = ChangeSet::new("", "");
         let expected_line_number: usize = 3;
         cs.add_line(expected_line_number);
-        assert!(cs.ranges().iter().find(|r| r.contains(&expected_line_number)).is_some());
+        assert!(cs.ranges().iter().any(|r| r.contains(&expected_line_number)));
     }
 
     #[test]
@@ -77,7 +213,7 @@ mod tests {
     #[test]
     fn contains_two_ranges_for_two_nonconsecutive_lines(){
         let mut cs = ChangeSet::new("", "");
-        vec![1, 3].iter().for_each(|lino| cs.add_line(*lino as usize));
+        [1, 3].iter().for_each(|lino| cs.add_line(*lino as usize));
         assert_eq!(cs.ranges().len(), 2);
     }
 
@@ -87,7 +223,7 @@ mod tests {
         (1..=3).for_each(|lino| cs.add_line(lino as usize));
         dbg!(cs.ranges());
         assert_eq!(cs.ranges().len(), 1);
-        (1..=3).for_each(|lino| assert!(cs.ranges().iter().find(|r| r.contains(&lino)).is_some()))
+        (1..=3).for_each(|lino| assert!(cs.ranges().iter().any(|r| r.contains(&lino))))
     }
 
     #[test]
@@ -97,8 +233,18 @@ mod tests {
         cs.add_line(4);
         dbg!(cs.ranges());
         assert_eq!(cs.ranges().len(), 2);
-        vec![1, 2, 4].iter()
-            .for_each(|lino| assert!(cs.ranges().iter().find(|r| r.contains(&lino)).is_some()))
+        [1, 2, 4].iter()
+            .for_each(|lino| assert!(cs.ranges().iter().any(|r| r.contains(lino))))
+    }
+
+    #[test]
+    fn ranges_within_gap_are_merged() {
+        let mut cs = ChangeSet::new("", "");
+        [1, 2, 5, 6, 10].iter().for_each(|lino| cs.add_line(*lino));
+        assert_eq!(cs.ranges_with_gap(0), cs.ranges());
+        assert_eq!(cs.ranges_with_gap(1), vec![1..3, 5..7, 10..11]);
+        assert_eq!(cs.ranges_with_gap(2), vec![1..7, 10..11]);
+        assert_eq!(cs.ranges_with_gap(3), vec![1..11]);
     }
 
     #[test]
@@ -111,7 +257,7 @@ mod tests {
 
         dbg!(cs.ranges());
         for v in &expected_line_numbers {
-            assert!(cs.ranges().iter().find(|r| r.contains(v)).is_some());
+            assert!(cs.ranges().iter().any(|r| r.contains(v)));
         }
     }
 
@@ -139,11 +285,158 @@ mod tests {
             println("%s", "foo");
         }
         "#};
-        let mut cs = ChangeSet::new("main.c", &code);
+        let mut cs = ChangeSet::new("main.c", code);
         cs.add_line(2);
         let all_ranges = cs.text_ranges().join("\n");
         dbg!(&all_ranges);
         assert!(all_ranges.contains("println"));
     }
 
+    #[test]
+    fn bounding_range_spans_first_to_last_line() {
+        let code: String = (0..10).map(|i| format!("line {}\n", i)).collect();
+        let mut cs = ChangeSet::new("main.c", &code);
+        cs.add_line(2);
+        cs.add_line(7);
+        assert_eq!(cs.bounding_range(), Some(2..8));
+        let text = cs.bounding_text().unwrap();
+        assert!(text.starts_with("line 2\n"));
+        assert!(text.ends_with("\nline 7"));
+        assert_eq!(text.lines().count(), 6);
+    }
+
+    #[test]
+    fn bounding_range_of_empty_change_set_is_none() {
+        let cs = ChangeSet::new("", "");
+        assert!(cs.bounding_range().is_none());
+        assert!(cs.bounding_text().is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn change_set_survives_json_round_trip() {
+        let mut cs = ChangeSet::new("src/main.c", "int a;\nint b;\n");
+        cs.add_line(1);
+        let json = serde_json::to_string(&cs).unwrap();
+        let restored: ChangeSet = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.filename, cs.filename);
+        assert_eq!(restored.code, cs.code);
+        assert_eq!(restored.lines, cs.lines);
+    }
+
+    #[test]
+    fn change_sets_compare_lines_in_any_order() {
+        let mut first = ChangeSet::new("main.c", "int a;\nint b;\nint c;\n");
+        let mut second = first.clone();
+        first.add_line(0);
+        first.add_line(2);
+        second.add_line(2);
+        second.add_line(0);
+        assert_eq!(first, second);
+        second.add_line(1);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn change_sets_with_different_status_differ() {
+        let added = ChangeSet::new("main.c", "int a;\n").with_status(ChangeStatus::Added);
+        let deleted = added.clone().with_status(ChangeStatus::Deleted);
+        assert_ne!(added, deleted);
+    }
+
+    #[test]
+    fn display_shows_filename_and_ranges() {
+        let mut cs = ChangeSet::new("main.c", "int a;\nint b;\nint c;\n");
+        cs.add_line(0);
+        cs.add_line(2);
+        assert_eq!(cs.to_string(), "main.c\n@@ 0..1 @@\nint a;\n@@ 2..3 @@\nint c;\n");
+    }
+
+    #[test]
+    fn out_of_order_lines_give_sorted_ranges() {
+        let mut shuffled = ChangeSet::new("", "");
+        [5, 3, 4, 9, 1].iter().for_each(|lino| shuffled.add_line(*lino));
+        let mut sorted = ChangeSet::new("", "");
+        [1, 3, 4, 5, 9].iter().for_each(|lino| sorted.add_line(*lino));
+        assert_eq!(shuffled.ranges(), vec![1..2, 3..6, 9..10]);
+        assert_eq!(shuffled.ranges(), sorted.ranges());
+    }
+
+    #[test]
+    fn duplicate_lines_give_same_ranges() {
+        let mut cs = ChangeSet::new("", "");
+        cs.lines = vec![4, 2, 3, 2, 4];
+        assert_eq!(cs.ranges(), vec![2..5]);
+    }
+
+    #[test]
+    fn repeated_line_is_added_once() {
+        let mut cs = ChangeSet::new("", "");
+        (0..3).for_each(|_| cs.add_line(3));
+        assert_eq!(cs.lines, vec![3]);
+        assert_eq!(cs.ranges(), vec![3..4]);
+    }
+
+    #[test]
+    fn text_ranges_keep_line_breaks() {
+        let code = indoc! {r#"
+        #include <stdio.h>
+        int main() {
+            println("%s", "foo");
+        }
+        "#};
+        let mut cs = ChangeSet::new("main.c", code);
+        (1..4).for_each(|lino| cs.add_line(lino));
+        assert_eq!(cs.text_ranges(), vec!["int main() {\n    println(\"%s\", \"foo\");\n}"]);
+    }
+
+    #[test]
+    fn lines_past_the_end_are_ignored() {
+        let mut cs = ChangeSet::new("main.c", "int a;\nint b;\n");
+        cs.add_line(1);
+        cs.add_line(2);
+        cs.add_line(7);
+        assert_eq!(cs.text_ranges(), vec!["int b;"]);
+        assert_eq!(cs.text_ranges_with_context(1, 1), vec!["int a;\nint b;"]);
+    }
+
+    #[test]
+    fn crlf_line_endings_are_preserved() {
+        let code = "int a;\r\nint b;\r\nint c;\r\n";
+        let mut cs = ChangeSet::new("main.c", code);
+        assert_eq!(cs.code.len(), 3);
+        cs.add_line(0);
+        cs.add_line(1);
+        assert_eq!(cs.text_ranges(), vec!["int a;\r\nint b;"]);
+        assert_eq!(cs.bounding_text().unwrap(), "int a;\r\nint b;");
+        assert_eq!(cs.text_ranges_with_context(0, 1), vec!["int a;\r\nint b;\r\nint c;"]);
+    }
+
+    #[test]
+    fn from_lines_matches_new() {
+        let code = "int a;\nint b;\nint c;\n";
+        let mut from_str = ChangeSet::new("main.c", code);
+        let mut from_lines = ChangeSet::from_lines("main.c", code.lines().map(String::from).collect());
+        for cs in [&mut from_str, &mut from_lines] {
+            cs.add_line(1);
+            cs.add_line(2);
+        }
+        assert_eq!(from_str.text_ranges(), from_lines.text_ranges());
+        assert_eq!(from_str, from_lines);
+    }
+
+    #[test]
+    fn context_is_added_and_overlapping_snippets_merged() {
+        let code: String = (0..10).map(|i| format!("line {}\n", i)).collect();
+        let mut cs = ChangeSet::new("main.c", &code);
+        cs.add_line(0);
+        cs.add_line(4);
+        cs.add_line(6);
+        assert_eq!(cs.text_ranges_with_context(1, 1), vec![
+            "line 0\nline 1",
+            "line 3\nline 4\nline 5\nline 6\nline 7",
+        ]);
+        assert_eq!(cs.text_ranges_with_context(0, 0), cs.text_ranges());
+        assert_eq!(cs.text_ranges_with_context(20, 20).len(), 1);
+    }
 }
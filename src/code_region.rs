@@ -1,8 +1,36 @@
 use std::ops::Range;
-use tree_sitter::{Parser, Point, Node, Tree};
+use std::path::Path;
+use thiserror::Error;
+use tree_sitter::{InputEdit, Language, LanguageError, Parser, Point, Node, Query, QueryCursor, QueryError, Tree};
 
+/// Whether the half-open ranges share at least one element. Line and byte ranges throughout
+/// `CodeRegion` are half-open, so `0..3` covers lines 0, 1 and 2 and an empty range
+/// intersects nothing.
 fn has_intersection(first: Range<usize>, second: Range<usize>) -> bool {
-    second.contains(&first.start) || first.contains(&second.start)
+    !first.is_empty() && !second.is_empty() && first.start < second.end && second.start < first.end
+}
+
+/// Node kinds of function definitions in the linked grammars.
+const FUNCTION_KINDS: &[&str] = &["function_definition", "function_item"];
+
+/// Node kinds of comments in the linked grammars.
+const COMMENT_KINDS: &[&str] = &["comment", "line_comment", "block_comment"];
+
+/// An extracted syntax node with its location. Lines are zero-based and both inclusive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractedNode {
+    pub code: String,
+    pub kind: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+#[derive(Error, Debug)]
+pub enum CodeRegionError {
+    #[error("the grammar is incompatible with the linked tree-sitter")]
+    Language(#[from] LanguageError),
+    #[error("parsing was cancelled before it finished")]
+    Parse,
 }
 
 pub struct CodeRegion {
@@ -11,31 +39,118 @@ pub struct CodeRegion {
 
 }
 
-impl CodeRegion{
-    pub fn new(code: &str) -> CodeRegion {
+/// Builds `CodeRegion`s of one language with a single parser, so parsing many files or
+/// revisions doesn't set up a new parser each time.
+pub struct CodeRegionFactory {
+    parser: Parser,
+}
 
+impl CodeRegionFactory {
+    pub fn new(language: Language) -> CodeRegionFactory {
+        CodeRegionFactory::try_new(language).expect("Error loading grammar")
+    }
+
+    pub fn try_new(language: Language) -> Result<CodeRegionFactory, CodeRegionError> {
         let mut parser = Parser::new();
-        parser.set_language(tree_sitter_c::language()).expect("Error loading C grammar");
-        let tree = parser.parse(code, None).unwrap();
+        parser.set_language(language)?;
+        Ok(CodeRegionFactory{parser})
+    }
+
+    /// Give up parsing after `micros` microseconds, so pathological input like huge minified
+    /// files can't stall a batch job. A parse that times out is a `CodeRegionError::Parse`
+    /// from `try_parse`, while `parse` and `reparse` panic. 0 disables the timeout.
+    pub fn with_timeout(mut self, micros: u64) -> CodeRegionFactory {
+        self.parser.set_timeout_micros(micros);
+        self
+    }
+
+    pub fn parse(&mut self, code: &str) -> CodeRegion {
+        self.try_parse(code).unwrap()
+    }
+
+    pub fn try_parse(&mut self, code: &str) -> Result<CodeRegion, CodeRegionError> {
+        let tree = self.parser.parse(code, None).ok_or(CodeRegionError::Parse)?;
 
-        CodeRegion{
+        Ok(CodeRegion{
             code: code.into(),
             tree,
+        })
+    }
+
+    /// Replace the code of `region` by `new_code`, reusing the unchanged parts of its old
+    /// tree; see `CodeRegion::reparse` for how `edits` must be given.
+    pub fn reparse(&mut self, region: &mut CodeRegion, new_code: &str, edits: &[InputEdit]) {
+        for edit in edits {
+            region.tree.edit(edit);
         }
+        region.tree = self.parser.parse(new_code, Some(&region.tree)).unwrap();
+        region.code = new_code.into();
     }
+}
 
-    fn extract_next_from_range(&self, range: Range<usize>) -> Option<Node>{
-        let mut cursor = self.tree.walk();
-        cursor.goto_first_child_for_point(Point::new(range.start, 0));
+impl CodeRegion{
+    /// Parse C code. Only available with the `c-grammar` feature; other grammars are passed to
+    /// `with_language`.
+    #[cfg(feature = "c-grammar")]
+    pub fn new(code: &str) -> CodeRegion {
+        CodeRegion::with_language(code, tree_sitter_c::language())
+    }
+
+    pub fn with_language(code: &str, language: Language) -> CodeRegion {
+        CodeRegionFactory::new(language).parse(code)
+    }
+
+    /// Like `new`, but an error instead of a panic if the code can't be parsed.
+    #[cfg(feature = "c-grammar")]
+    pub fn try_new(code: &str) -> Result<CodeRegion, CodeRegionError> {
+        CodeRegion::try_with_language(code, tree_sitter_c::language())
+    }
+
+    pub fn try_with_language(code: &str, language: Language) -> Result<CodeRegion, CodeRegionError> {
+        CodeRegionFactory::try_new(language)?.try_parse(code)
+    }
+
+    /// Like `try_new`, but parsing gives up after `micros` microseconds with an error rather
+    /// than a region without a usable tree.
+    #[cfg(feature = "c-grammar")]
+    pub fn with_timeout(code: &str, micros: u64) -> Result<CodeRegion, CodeRegionError> {
+        CodeRegionFactory::try_new(tree_sitter_c::language())?.with_timeout(micros).try_parse(code)
+    }
+
+    /// Parse `code` with the grammar matching the extension of `path`. None if the extension
+    /// is unknown or the crate doesn't link a grammar for it, see `Language::grammar`.
+    pub fn from_path<P: AsRef<Path> + ?Sized>(path: &P, code: &str) -> Option<CodeRegion> {
+        let grammar = crate::language::Language::from_path(path)?.grammar()?;
+        Some(CodeRegion::with_language(code, grammar))
+    }
 
-        let current_node = cursor.node();
-        let line_range = current_node.start_position().row..current_node.end_position().row;
+    /// Replace the code by `new_code`, reparsing only what `edits` touched. The edits describe
+    /// how the old code turned into `new_code` and are applied in order, each relative to the
+    /// code left by the previous one. `start_byte` and `old_end_byte` are byte offsets before
+    /// the edit, `new_end_byte` after it, and the positions are zero-based rows with columns
+    /// in bytes, not characters. Wrong edits don't fail but silently yield a wrong tree.
+    pub fn reparse(&mut self, new_code: &str, edits: &[InputEdit]) {
+        CodeRegionFactory::new(self.tree.language()).reparse(self, new_code, edits)
+    }
+
+    /// Whether the parse tree contains syntax errors, e.g. because the code is a truncated
+    /// hunk rather than a complete file. Extractions from such a tree may be incomplete.
+    pub fn has_errors(&self) -> bool {
+        self.tree.root_node().has_error()
+    }
 
-        if has_intersection(range.clone(), line_range) {
-            Some(current_node.clone())
-        } else {
-            None
+    /// Code of the top-level node containing the byte at `offset`. Unlike the line-based
+    /// extraction this tells apart several statements on the same line.
+    pub fn extract_at_byte(&self, offset: usize) -> Option<String> {
+        let root = self.tree.root_node();
+        let mut node = root.descendant_for_byte_range(offset, offset)?;
+        if node == root {
+            return None;
         }
+        while let Some(parent) = node.parent().filter(|parent| *parent != root) {
+            node = parent;
+        }
+        Some(self.extract_code_from_node(node))
     }
 
     fn extract_code_from_node(&self, function_node: Node) -> String {
@@ -44,23 +159,79 @@ impl CodeRegion{
         String::from_utf8_lossy(&self.code.as_bytes()[start..end]).to_string()
     }
 
-    pub fn extract_compounds_by(&self, range: Range<usize>, filter: fn(node: &Node) -> bool) -> Vec<String> {
-        let mut compounds = vec![];
-        let mut next_range = range.clone();
-        while !self.code.is_empty() && !next_range.is_empty() {
-            match self.extract_next_from_range(next_range.clone()) {
-                Some(entity) if filter(&entity) => {
-                    compounds.push(self.extract_code_from_node(entity));
-                    next_range = (entity.range().end_point.row+1)..next_range.end;
-                },
-                Some(entity) => {
-                    dbg!(entity.kind());
-                    next_range = (entity.range().end_point.row+1)..next_range.end;
-                },
-                None => break
+    fn extracted_node(&self, node: Node) -> ExtractedNode {
+        ExtractedNode{
+            code: self.extract_code_from_node(node),
+            kind: node.kind().to_string(),
+            start_line: node.start_position().row,
+            end_line: node.end_position().row,
+        }
+    }
+
+    pub fn extract_compounds_by<F>(&self, range: Range<usize>, filter: F) -> Vec<String>
+        where F: Fn(&Node) -> bool
+    {
+        self.extract_nodes_by(range, filter).into_iter().map(|node| node.code).collect()
+    }
+
+    /// Like `extract_compounds_by`, but keeps the kind and lines of each node.
+    pub fn extract_nodes_by<F>(&self, range: Range<usize>, filter: F) -> Vec<ExtractedNode>
+        where F: Fn(&Node) -> bool
+    {
+        let byte_range = self.line_offset(range.start)..self.line_offset(range.end);
+        self.extract_nodes_by_bytes(byte_range, filter)
+    }
+
+    /// Like `extract_compounds_by` for the nodes overlapping `byte_range` instead of a range of
+    /// lines, which tells apart several statements on the same line.
+    pub fn extract_compounds_by_bytes<F>(&self, byte_range: Range<usize>, filter: F) -> Vec<String>
+        where F: Fn(&Node) -> bool
+    {
+        self.extract_nodes_by_bytes(byte_range, filter).into_iter().map(|node| node.code).collect()
+    }
+
+    fn extract_nodes_by_bytes<F>(&self, byte_range: Range<usize>, filter: F) -> Vec<ExtractedNode>
+        where F: Fn(&Node) -> bool
+    {
+        let mut nodes = vec![];
+        self.collect_outermost_by(self.tree.root_node(), &byte_range, &filter, &mut nodes);
+        nodes.into_iter().map(|node| self.extracted_node(node)).collect()
+    }
+
+    /// Byte offset at which `line` starts, or the length of the code for lines past its end.
+    fn line_offset(&self, line: usize) -> usize {
+        match line {
+            0 => 0,
+            _ => self.code.match_indices('\n').nth(line - 1).map_or(self.code.len(), |(index, _)| index + 1),
+        }
+    }
+
+    /// Collect the outermost descendants of `node` overlapping `byte_range` that satisfy
+    /// `filter`, so definitions nested in e.g. an `#ifdef` block or a namespace are found. The
+    /// tree is walked with a cursor instead of recursion, so deeply nested code can't overflow
+    /// the stack.
+    fn collect_outermost_by<'a, F>(&self, node: Node<'a>, byte_range: &Range<usize>, filter: &F, found: &mut Vec<Node<'a>>)
+        where F: Fn(&Node) -> bool
+    {
+        let mut cursor = node.walk();
+        if !cursor.goto_first_child() {
+            return;
+        }
+        loop {
+            let child = cursor.node();
+            if has_intersection(byte_range.clone(), child.byte_range()) {
+                if filter(&child) {
+                    found.push(child);
+                } else if cursor.goto_first_child() {
+                    continue;
+                }
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() || cursor.node() == node {
+                    return;
+                }
             }
         }
-        compounds
     }
 
     pub fn extract_compound(&self, range: Range<usize>) -> Vec<String> {
@@ -70,6 +241,216 @@ impl CodeRegion{
     pub fn extract_functions(&self, range: Range<usize>) -> Vec<String> {
         self.extract_compounds_by(range, |n| n.kind() == "function_definition")
     }
+
+    /// Like `extract_by_kind` for nodes of any of `kinds`, collected in a single pass and in
+    /// source order.
+    pub fn extract_kinds(&self, range: Range<usize>, kinds: &[&str]) -> Vec<String> {
+        self.extract_compounds_by(range, |n| kinds.contains(&n.kind()))
+    }
+
+    /// Comments touching `range`, including those inside function bodies.
+    pub fn extract_comments(&self, range: Range<usize>) -> Vec<String> {
+        self.extract_kinds(range, COMMENT_KINDS)
+    }
+
+    /// Struct definitions, i.e. `struct_specifier`s with a body, touching `range`. Structs
+    /// that are only referred to, as in `struct foo *p;`, are left out.
+    pub fn extract_structs(&self, range: Range<usize>) -> Vec<String> {
+        self.extract_compounds_by(range, |n| n.kind() == "struct_specifier" && n.child_by_field_name("body").is_some())
+    }
+
+    pub fn extract_typedefs(&self, range: Range<usize>) -> Vec<String> {
+        self.extract_compounds_by(range, |n| n.kind() == "type_definition")
+    }
+
+    /// Like `extract_functions` for any node kind, e.g. `"function_item"` in Rust, whose
+    /// names differ between grammars.
+    pub fn extract_by_kind(&self, range: Range<usize>, kind: &str) -> Vec<String> {
+        self.extract_compounds_by(range, |n| n.kind() == kind)
+    }
+
+    fn node_name(&self, node: Node) -> Option<String> {
+        if let Some(name) = node.child_by_field_name("name") {
+            return Some(self.extract_code_from_node(name));
+        }
+
+        let mut declarator = node.child_by_field_name("declarator");
+        while let Some(current) = declarator {
+            if current.kind().ends_with("identifier") || current.kind() == "destructor_name" {
+                return Some(self.extract_code_from_node(current));
+            }
+            declarator = match current.kind() {
+                "parenthesized_declarator" => current.named_child(0),
+                _ => current.child_by_field_name("declarator"),
+            };
+        }
+        None
+    }
+
+    fn covers_line(node: &Node, line: usize) -> bool {
+        let (start, end) = (node.start_position(), node.end_position());
+        start.row <= line && (line < end.row || (line == end.row && end.column > 0))
+    }
+
+    /// Name of the top-level definition (function, type, global, ...) containing `line`.
+    pub fn top_level_name_at(&self, line: usize) -> Option<String> {
+        let root = self.tree.root_node();
+        let mut cursor = root.walk();
+        let definition = root.children(&mut cursor).find(|n| CodeRegion::covers_line(n, line))?;
+        self.node_name(definition)
+    }
+
+    fn scope_kind(node: &Node) -> Option<&'static str> {
+        match node.kind() {
+            "namespace_definition" => Some("namespace"),
+            "class_specifier" => Some("class"),
+            "struct_specifier" if node.child_by_field_name("body").is_some() => Some("struct"),
+            "function_definition" => match node.parent().map(|p| p.kind()) {
+                Some("field_declaration_list") => Some("method"),
+                _ => Some("function"),
+            },
+            _ => None,
+        }
+    }
+
+    fn collect_descendants_by<'a>(&self, node: Node<'a>, range: &Range<usize>, filter: fn(node: &Node) -> bool,
+                                   depth: usize, max_depth: usize, found: &mut Vec<Node<'a>>) {
+        let line_range = node.start_position().row..node.end_position().row + 1;
+        if !has_intersection(range.clone(), line_range) {
+            return;
+        }
+        if filter(&node) {
+            found.push(node);
+        }
+        if depth == max_depth {
+            return;
+        }
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            self.collect_descendants_by(child, range, filter, depth + 1, max_depth, found);
+        }
+    }
+
+    /// Like `extract_compounds_by`, but also finds nested nodes, descending at most `max_depth`
+    /// levels below the root of the syntax tree.
+    pub fn extract_nested_by(&self, range: Range<usize>, filter: fn(node: &Node) -> bool, max_depth: usize) -> Vec<String> {
+        let mut found = vec![];
+        if !self.code.is_empty() {
+            self.collect_descendants_by(self.tree.root_node(), &range, filter, 0, max_depth, &mut found);
+        }
+        found.into_iter().map(|node| self.extract_code_from_node(node)).collect()
+    }
+
+    /// Text of every node captured by the tree-sitter `query`, in document order. The query
+    /// is compiled for the grammar the code was parsed with; the error locates syntax mistakes
+    /// and unknown node kinds.
+    pub fn query(&self, query: &str) -> Result<Vec<String>, QueryError> {
+        let query = Query::new(self.tree.language(), query)?;
+        let mut cursor = QueryCursor::new();
+        Ok(cursor.captures(&query, self.tree.root_node(), self.code.as_bytes())
+            .map(|(found, index)| self.extract_code_from_node(found.captures[index].node))
+            .collect())
+    }
+
+    /// All nodes of the given kind in document order.
+    fn nodes_of_kind(&self, kind: &str) -> Vec<Node<'_>> {
+        let mut nodes = vec![];
+        let mut cursor = self.tree.walk();
+        let mut visited_children = false;
+        loop {
+            let node = cursor.node();
+            if !visited_children && node.kind() == kind {
+                nodes.push(node);
+            }
+            if !visited_children && cursor.goto_first_child() {
+                continue;
+            }
+            if cursor.goto_next_sibling() {
+                visited_children = false;
+            } else if cursor.goto_parent() {
+                visited_children = true;
+            } else {
+                break;
+            }
+        }
+        nodes
+    }
+
+    /// Function definitions of every linked grammar, each kind in document order.
+    fn function_nodes(&self) -> Vec<Node<'_>> {
+        FUNCTION_KINDS.iter().flat_map(|kind| self.nodes_of_kind(kind)).collect()
+    }
+
+    fn innermost_at<'a>(functions: &[Node<'a>], line: usize) -> Option<Node<'a>> {
+        functions.iter().rev().find(|f| CodeRegion::covers_line(f, line)).copied()
+    }
+
+    /// Name of the innermost function containing the zero-based `line`, or None for lines
+    /// outside any function, e.g. includes and globals.
+    pub fn function_name_at(&self, line: usize) -> Option<String> {
+        let function = CodeRegion::innermost_at(&self.function_nodes(), line)?;
+        self.node_name(function)
+    }
+
+    /// The distinct functions containing any of the zero-based `lines`, innermost per line and
+    /// in document order.
+    pub fn enclosing_functions(&self, lines: &[usize]) -> Vec<String> {
+        let functions = self.function_nodes();
+        let mut enclosing: Vec<Node> = lines.iter()
+            .filter_map(|line| CodeRegion::innermost_at(&functions, *line))
+            .collect();
+        enclosing.sort_by_key(|node| node.start_byte());
+        enclosing.dedup();
+        enclosing.into_iter().map(|node| self.extract_code_from_node(node)).collect()
+    }
+
+    /// Name and body text of every function defined in the code.
+    pub fn function_definitions(&self) -> Vec<(String, String)> {
+        self.nodes_of_kind("function_definition").into_iter()
+            .filter_map(|node| {
+                let name = self.node_name(node)?;
+                let body = node.child_by_field_name("body")?;
+                Some((name, self.extract_code_from_node(body)))
+            })
+            .collect()
+    }
+
+    /// Name and parameter texts of every function declarator, including those of declarations
+    /// and function pointer typedefs.
+    pub fn function_signatures(&self) -> Vec<(String, Vec<String>)> {
+        self.nodes_of_kind("function_declarator").into_iter()
+            .filter_map(|node| {
+                let name = self.node_name(node)?;
+                let parameter_list = node.child_by_field_name("parameters")?;
+                let mut cursor = parameter_list.walk();
+                let parameters = parameter_list.named_children(&mut cursor)
+                    .filter(|p| p.kind() != "comment")
+                    .map(|p| self.extract_code_from_node(p))
+                    .collect();
+                Some((name, parameters))
+            })
+            .collect()
+    }
+
+    /// Named scopes enclosing `line`, ordered from the outermost to the innermost.
+    pub fn scope_chain(&self, line: usize) -> Vec<(String, String)> {
+        let column = self.code.lines().nth(line)
+            .map_or(0, |l| l.len() - l.trim_start().len());
+        let point = Point::new(line, column);
+
+        let mut chain = vec![];
+        let mut current = self.tree.root_node().descendant_for_point_range(point, point);
+        while let Some(node) = current {
+            if let Some(kind) = CodeRegion::scope_kind(&node) {
+                if let Some(name) = self.node_name(node) {
+                    chain.push((kind.to_string(), name));
+                }
+            }
+            current = node.parent();
+        }
+        chain.reverse();
+        chain
+    }
 }
 
 #[cfg(test)]
@@ -78,37 +459,60 @@ mod tests {
     use indoc::indoc;
 
     #[test]
+    #[cfg(feature = "c-grammar")]
     fn get_empty_vec_from_empty_content_test() {
         let code = CodeRegion::new("");
         assert!(code.extract_compound(0..1).is_empty());
     }
 
     #[test]
+    #[cfg(feature = "c-grammar")]
     fn get_function_if_content_contains_single_function_test() {
         let content = "int main(int argc, char** argv) {return 0;}";
-        let code = CodeRegion::new(&content);
+        let code = CodeRegion::new(content);
         assert!(!code.extract_compound(0..1).is_empty())
     }
 
     #[test]
+    #[cfg(feature = "c-grammar")]
     fn get_function_if_content_contains_single_function_and_region_is_empty_test() {
         let content = "int main(int argc, char** argv) {return 0;}";
-        let code = CodeRegion::new(&content);
+        let code = CodeRegion::new(content);
         assert!(code.extract_compound(1..1).is_empty())
     }
 
     #[test]
+    #[cfg(feature = "c-grammar")]
     fn get_single_line_function_from_multi_function_content() {
         let content = indoc!{"
         void foo() {}
         int main() {foo()};
         "};
-        let all_functions = CodeRegion::new(&content).extract_compound(0..1);
+        let all_functions = CodeRegion::new(content).extract_compound(0..1);
         let functions_containing_main = all_functions.iter().find(|c| c.contains("main"));
         assert!(functions_containing_main.is_none());
     }
 
     #[test]
+    #[cfg(feature = "c-grammar")]
+    fn get_both_single_line_functions_on_the_same_line() {
+        let code = CodeRegion::new("int f() { return 1; } int g() { return 2; }\n");
+        assert_eq!(code.extract_functions(0..1), vec!["int f() { return 1; }", "int g() { return 2; }"]);
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn extract_statements_by_bytes() {
+        let content = "int a = 1; int b = 2; int c = 3;\n";
+        let code = CodeRegion::new(content);
+        let second = content.find("int b").unwrap();
+        assert_eq!(code.extract_compounds_by_bytes(second..second + 3, |_| true), vec!["int b = 2;"]);
+        assert_eq!(code.extract_compounds_by_bytes(second..content.len(), |_| true), vec!["int b = 2;", "int c = 3;"]);
+        assert!(code.extract_compounds_by_bytes(second..second, |_| true).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
     fn get_function_from_multi_function_content() {
         let content = indoc!{r#"
         #include <stdio.h>
@@ -117,12 +521,13 @@ mod tests {
         }
         int main() {foo()};
         "#};
-        let all_functions = CodeRegion::new(&content).extract_compound(2..4);
+        let all_functions = CodeRegion::new(content).extract_compound(2..4);
         let functions_containing_main = all_functions.iter().find(|c| c.contains("main"));
         assert!(functions_containing_main.is_none());
     }
 
     #[test]
+    #[cfg(feature = "c-grammar")]
     fn get_multi_line_function_with_narrow_range() {
         let content = indoc!{r#"
         #include <stdio.h>
@@ -131,12 +536,13 @@ mod tests {
         }
         int main() {foo()};
         "#};
-        let all_functions = CodeRegion::new(&content).extract_compound(2..3);
+        let all_functions = CodeRegion::new(content).extract_compound(2..3);
         let functions_containing_main = all_functions.iter().find(|c| c.contains("void foo()"));
         assert!(functions_containing_main.is_some());
     }
 
     #[test]
+    #[cfg(feature = "c-grammar")]
     fn get_multiple_functions_with_wider_range() {
         let content = indoc!{r#"
         #include <stdio.h>
@@ -145,11 +551,12 @@ mod tests {
         }
         int main() {foo()};
         "#};
-        let all_functions = CodeRegion::new(&content).extract_compound(2..5);
+        let all_functions = CodeRegion::new(content).extract_compound(2..5);
         assert!(all_functions.len() == 2);
     }
 
     #[test]
+    #[cfg(feature = "c-grammar")]
     fn extract_struct_from_range() {
         let content = indoc!{r#"
         #include <stdio.h>
@@ -159,12 +566,75 @@ mod tests {
             foo a;
         }
         "#};
-        let all_regions = CodeRegion::new(&content).extract_compound(1..4);
+        let all_regions = CodeRegion::new(content).extract_compound(1..4);
         dbg!(&all_regions);
         assert!(all_regions.len() == 2);
     }
 
     #[test]
+    #[cfg(all(feature = "c-grammar", feature = "rust-grammar"))]
+    fn extract_comments_from_range() {
+        let content = indoc!{r#"
+        /* security: check the length */
+        int f(int n) {
+            // FIXME off by one
+            return n + 1;
+        }
+        "#};
+        let region = CodeRegion::new(content);
+        assert_eq!(region.extract_comments(0..5), vec!["/* security: check the length */", "// FIXME off by one"]);
+        assert_eq!(region.extract_comments(3..5), Vec::<String>::new());
+
+        let region = CodeRegion::with_language("// why\nfn f() {}\n", tree_sitter_rust::language());
+        assert_eq!(region.extract_comments(0..2), vec!["// why"]);
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn extract_structs_and_typedefs_from_range() {
+        let content = indoc!{r#"
+        struct point { int x; };
+        typedef struct { int y; } bar;
+
+        void main() {
+            struct point *p;
+        }
+        "#};
+        let region = CodeRegion::new(content);
+        assert_eq!(region.extract_structs(0..6), vec!["struct point { int x; }", "struct { int y; }"]);
+        assert_eq!(region.extract_typedefs(0..6), vec!["typedef struct { int y; } bar;"]);
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn crlf_code_is_extracted_byte_for_byte() {
+        let content = "int a;\r\nint f() {\r\n    return 1;\r\n}\r\n";
+        let region = CodeRegion::new(content);
+        assert_eq!(region.extract_functions(1..2), vec!["int f() {\r\n    return 1;\r\n}"]);
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn extract_several_kinds_in_source_order() {
+        let content = indoc!{r#"
+        #define LIMIT 10
+        typedef int count;
+        int global;
+
+        count f() {
+            return LIMIT;
+        }
+        "#};
+        let region = CodeRegion::new(content);
+        assert_eq!(region.extract_kinds(0..7, &["function_definition", "type_definition", "preproc_def"]), vec![
+            "#define LIMIT 10\n",
+            "typedef int count;",
+            "count f() {\n    return LIMIT;\n}",
+        ]);
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
     fn extract_only_functions_from_range() {
         let content = indoc!{r#"
         #include <stdio.h>
@@ -174,12 +644,322 @@ mod tests {
             foo a;
         }
         "#};
-        let all_regions = CodeRegion::new(&content).extract_functions(1..5);
+        let all_regions = CodeRegion::new(content).extract_functions(1..5);
         dbg!(&all_regions);
         assert!(all_regions.len() == 1);
     }
 
 
+    #[test]
+    fn scope_chain_of_method_inside_class() {
+        let content = indoc!{r#"
+        namespace outer {
+        class Widget {
+        public:
+            int size() {
+                return 1;
+            }
+        };
+        }
+        "#};
+        let code = CodeRegion::with_language(content, tree_sitter_cpp::language());
+        let expected: Vec<(String, String)> = vec![
+            ("namespace".into(), "outer".into()),
+            ("class".into(), "Widget".into()),
+            ("method".into(), "size".into()),
+        ];
+        assert_eq!(code.scope_chain(4), expected);
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn scope_chain_outside_any_scope_is_empty() {
+        let content = indoc!{r#"
+        #include <stdio.h>
+        int main() { return 0; }
+        "#};
+        assert!(CodeRegion::new(content).scope_chain(0).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn function_definitions_have_name_and_body() {
+        let content = indoc!{r#"
+        #include <stdio.h>
+        void foo() {
+            println("foo");
+        }
+        int main() { foo(); }
+        "#};
+        let definitions = CodeRegion::new(content).function_definitions();
+        let names: Vec<&str> = definitions.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["foo", "main"]);
+        assert!(definitions[0].1.contains("println"));
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn nested_extraction_finds_inner_blocks() {
+        let content = indoc!{r#"
+        int main() {
+            if (1) {
+                return 0;
+            }
+        }
+        "#};
+        let blocks = CodeRegion::new(content).extract_nested_by(2..3, |n| n.kind() == "compound_statement", 10);
+        assert_eq!(blocks.len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn nested_extraction_stops_at_max_depth() {
+        let nesting = 2000;
+        let content = format!("int main() {{ {} return 0; {} }}", "{".repeat(nesting), "}".repeat(nesting));
+        let code = CodeRegion::new(&content);
+        // The function body is at depth 2, below the translation unit and the function
+        // definition, and every brace nests one compound statement deeper.
+        let shallow = code.extract_nested_by(0..1, |n| n.kind() == "compound_statement", 5);
+        let deeper = code.extract_nested_by(0..1, |n| n.kind() == "compound_statement", 10);
+        assert_eq!(shallow.len(), 4);
+        assert_eq!(deeper.len(), 9);
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn deeply_nested_code_does_not_overflow_the_stack() {
+        let nesting = 100_000;
+        let content = format!("int main() {{ {} return 0; {} }}", "{".repeat(nesting), "}".repeat(nesting));
+        let code = CodeRegion::new(&content);
+        assert_eq!(code.extract_by_kind(0..1, "return_statement"), vec!["return 0;"]);
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn function_signatures_include_typedefs() {
+        let content = indoc!{r#"
+        typedef void (*fpt)(unsigned int i);
+        int add(int a, int b) { return a + b; }
+        "#};
+        let signatures = CodeRegion::new(content).function_signatures();
+        assert_eq!(signatures, vec![
+            ("fpt".to_string(), vec!["unsigned int i".to_string()]),
+            ("add".to_string(), vec!["int a".to_string(), "int b".to_string()]),
+        ]);
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn top_level_names_of_definitions() {
+        let content = indoc!{r#"
+        #include <stdio.h>
+        typedef void (*fpt)(unsigned int i);
+        int counter = 0;
+        void foo() {
+            counter++;
+        }
+        "#};
+        let code = CodeRegion::new(content);
+        assert_eq!(code.top_level_name_at(0), None);
+        assert_eq!(code.top_level_name_at(1).as_deref(), Some("fpt"));
+        assert_eq!(code.top_level_name_at(2).as_deref(), Some("counter"));
+        assert_eq!(code.top_level_name_at(4).as_deref(), Some("foo"));
+    }
+
+    #[test]
+    #[cfg(feature = "rust-grammar")]
+    fn extract_rust_functions_by_kind() {
+        let code = indoc!{"
+        struct Point { x: i32 }
+
+        fn origin() -> Point {
+            Point { x: 0 }
+        }
+        "};
+        let region = CodeRegion::with_language(code, tree_sitter_rust::language());
+        assert_eq!(region.extract_by_kind(0..5, "function_item"), vec!["fn origin() -> Point {\n    Point { x: 0 }\n}"]);
+        assert_eq!(region.extract_by_kind(0..5, "struct_item"), vec!["struct Point { x: i32 }"]);
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn factory_reuses_parser_across_regions() {
+        let mut factory = CodeRegionFactory::new(tree_sitter_c::language());
+        let first = factory.parse("int f() { return 1; }\n");
+        let second = factory.parse("int g() { return 2; }\n");
+        assert_eq!(first.extract_functions(0..1), vec!["int f() { return 1; }"]);
+        assert_eq!(second.extract_functions(0..1), vec!["int g() { return 2; }"]);
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn cancelled_parse_is_an_error() {
+        assert!(CodeRegion::try_new("int f() { return 1; }\n").is_ok());
+        let mut factory = CodeRegionFactory::try_new(tree_sitter_c::language()).unwrap();
+        let cancelled = std::sync::atomic::AtomicUsize::new(1);
+        unsafe { factory.parser.set_cancellation_flag(Some(&cancelled)) };
+        // The flag is only checked every few parse steps, so the code must not be trivial.
+        let code = "int f() { return 1; }\n".repeat(100);
+        assert!(matches!(factory.try_parse(&code), Err(CodeRegionError::Parse)));
+        unsafe { factory.parser.set_cancellation_flag(None) };
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn parse_exceeding_timeout_is_an_error() {
+        let code = "int f() { return 1; }\n".repeat(10_000);
+        assert!(matches!(CodeRegion::with_timeout(&code, 1), Err(CodeRegionError::Parse)));
+        assert!(CodeRegion::with_timeout("int f() { return 1; }\n", 10_000_000).is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn reparsed_region_sees_inserted_function() {
+        let old_code = "int f() { return 1; }\n";
+        let new_code = "int f() { return 1; }\nint g() { return 2; }\n";
+        let mut region = CodeRegion::new(old_code);
+        let edit = InputEdit{
+            start_byte: old_code.len(),
+            old_end_byte: old_code.len(),
+            new_end_byte: new_code.len(),
+            start_position: Point::new(1, 0),
+            old_end_position: Point::new(1, 0),
+            new_end_position: Point::new(2, 0),
+        };
+        region.reparse(new_code, &[edit]);
+        assert_eq!(region.extract_functions(0..2), vec!["int f() { return 1; }", "int g() { return 2; }"]);
+    }
+
+    #[test]
+    #[cfg(all(feature = "c-grammar", feature = "rust-grammar"))]
+    fn grammar_is_chosen_by_extension() {
+        let region = CodeRegion::from_path("src/lib.rs", "fn f() {}\n").unwrap();
+        assert_eq!(region.extract_by_kind(0..1, "function_item"), vec!["fn f() {}"]);
+        let region = CodeRegion::from_path("main.c", "void f() {}\n").unwrap();
+        assert_eq!(region.extract_functions(0..1), vec!["void f() {}"]);
+        assert!(CodeRegion::from_path("script.py", "pass\n").is_none());
+        assert!(CodeRegion::from_path("README", "").is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn broken_code_has_errors() {
+        assert!(CodeRegion::new("int main() {\n    return 0;\n").has_errors());
+        assert!(CodeRegion::new("int f(int a {\n}\n").has_errors());
+        assert!(!CodeRegion::new("int main() {\n    return 0;\n}\n").has_errors());
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn extract_function_inside_preprocessor_block() {
+        let content = indoc!{r#"
+        #include <stdio.h>
+        #ifdef DEBUG
+        void trace(int x) {
+            printf("%d\n", x);
+        }
+        #endif
+
+        int main() {
+            return 0;
+        }
+        "#};
+        let region = CodeRegion::new(content);
+        assert_eq!(region.extract_functions(3..4), vec!["void trace(int x) {\n    printf(\"%d\\n\", x);\n}"]);
+        assert_eq!(region.extract_functions(0..10).len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn extract_second_declaration_on_same_line() {
+        let content = "int a; int b;\n";
+        let region = CodeRegion::new(content);
+        assert_eq!(region.extract_at_byte(content.find('b').unwrap()).as_deref(), Some("int b;"));
+        assert_eq!(region.extract_at_byte(0).as_deref(), Some("int a;"));
+        assert_eq!(CodeRegion::new("").extract_at_byte(0), None);
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn extracted_nodes_carry_kind_and_lines() {
+        let content = indoc!{"
+        int a;
+
+        void f() {
+            a++;
+        }
+        "};
+        let nodes = CodeRegion::new(content).extract_nodes_by(0..5, |_| true);
+        assert_eq!(nodes, vec![
+            ExtractedNode{code: "int a;".into(), kind: "declaration".into(), start_line: 0, end_line: 0},
+            ExtractedNode{code: "void f() {\n    a++;\n}".into(), kind: "function_definition".into(), start_line: 2, end_line: 4},
+        ]);
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn query_captures_function_names() {
+        let content = indoc!{"
+        int add(int a, int b) { return a + b; }
+        int sub(int a, int b) { return a - b; }
+        "};
+        let region = CodeRegion::new(content);
+        let names = region.query("(function_definition declarator: (function_declarator declarator: (identifier) @name))");
+        assert_eq!(names.unwrap(), vec!["add", "sub"]);
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn invalid_query_is_an_error() {
+        let region = CodeRegion::new("int a;\n");
+        assert!(region.query("(function_definition").is_err());
+        assert!(region.query("(no_such_kind) @x").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn lines_are_mapped_to_function_names() {
+        let content = indoc!{r#"
+        #include <stdio.h>
+        int counter;
+        int first(void) {
+            return 1;
+        }
+
+        static char *second(int n) {
+            return NULL;
+        }
+        "#};
+        let region = CodeRegion::new(content);
+        let names: Vec<Option<String>> = (0..10).map(|line| region.function_name_at(line)).collect();
+        let first = Some("first".to_string());
+        let second = Some("second".to_string());
+        assert_eq!(names, vec![None, None, first.clone(), first.clone(), first, None, second.clone(), second.clone(), second, None]);
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn enclosing_functions_are_deduplicated() {
+        let content = indoc!{"
+        int g;
+
+        void first() {
+            g = 1;
+            g = 2;
+        }
+
+        void second() {
+            g = 3;
+        }
+        "};
+        let region = CodeRegion::new(content);
+        assert_eq!(region.enclosing_functions(&[8, 3, 0, 4]), vec![
+            "void first() {\n    g = 1;\n    g = 2;\n}",
+            "void second() {\n    g = 3;\n}",
+        ]);
+        assert!(region.enclosing_functions(&[0, 6]).is_empty());
+    }
+
     #[test]
     fn empty_empty_has_no_intersection_test() {
         assert!(!has_intersection(0..0, 0..0))
@@ -216,4 +996,20 @@ mod tests {
         assert!(!has_intersection(3..8, 0..3))
     }
 
+    #[test]
+    fn empty_range_has_no_intersection() {
+        assert!(!has_intersection(2..2, 0..5));
+        assert!(!has_intersection(0..5, 2..2));
+    }
+
+    #[test]
+    fn single_line_overlap() {
+        assert!(has_intersection(0..4, 3..8));
+        assert!(has_intersection(3..4, 3..4));
+    }
+
+    #[test]
+    fn identical_ranges_intersect() {
+        assert!(has_intersection(1..5, 1..5));
+    }
 }
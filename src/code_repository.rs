@@ -1,329 +1,2947 @@
-use regex::Regex;
-use git2::{Repository,Oid, DiffDelta, DiffHunk, DiffLine};
+use regex::{Regex, RegexSet};
+use git2::{Repository,Oid, Delta, DiffDelta, DiffFindOptions, DiffHunk, DiffLine, DiffLineType, DiffOptions, Patch, Sort};
 use thiserror::Error;
-use std::collections::HashMap;
-use crate::change_set::ChangeSet;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use crate::blob_cache::{BlobCache, CacheStats};
+use crate::change_set::{ChangeSet, ChangeStatus};
+use crate::code_region::CodeRegion;
+use crate::extract_options::{EofNewline, ExtractOptions, LineKind};
+use crate::generated::{has_generated_name, looks_generated};
+use crate::language::Language;
+use crate::scan::ScanBuilder;
+use std::ops::Range;
 use std::path::{Path,PathBuf};
+use std::cell::RefCell;
+use std::sync::Arc;
 
 
+/// Number of decoded blobs a `CodeRepository` keeps around by default.
+const DEFAULT_BLOB_CACHE_CAPACITY: usize = 64;
+
+/// Number of leading bytes of a blob judged by `looks_generated`, so large files aren't
+/// decoded in full just to be classified.
+const GENERATED_SAMPLE_SIZE: usize = 64 * 1024;
+
+/// Group of `changes_by_definition` for lines outside any named top-level definition.
+pub const FILE_LEVEL_DEFINITION: &str = "<file>";
+
+/// Minimal token similarity for two function bodies to count as the same function.
+#[cfg(feature = "c-grammar")]
+const RENAME_SIMILARITY: f64 = 0.8;
+
+#[cfg(feature = "c-grammar")]
+fn body_similarity(first: &str, second: &str) -> f64 {
+    let mut remaining: Vec<&str> = second.split_whitespace().collect();
+    let first_tokens: Vec<&str> = first.split_whitespace().collect();
+    let total = first_tokens.len() + remaining.len();
+    if total == 0 {
+        return 1.0;
+    }
+
+    let mut common = 0;
+    for token in first_tokens {
+        if let Some(position) = remaining.iter().position(|t| *t == token) {
+            remaining.swap_remove(position);
+            common += 1;
+        }
+    }
+    2.0 * common as f64 / total as f64
+}
+
+fn is_documentation(path: &Path) -> bool {
+    let in_docs_dir = path.components().any(|c| c.as_os_str() == "doc" || c.as_os_str() == "docs");
+    let is_text = matches!(path.extension().and_then(|e| e.to_str()), Some("md" | "rst" | "txt" | "adoc"));
+    in_docs_dir || is_text
+}
+
+/// A line of a commit's diff with its origin (`'+'`, `'-'` or `' '`) and 1-based line numbers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnnotatedLine {
+    pub path: PathBuf,
+    pub origin: char,
+    pub old_lineno: Option<usize>,
+    pub new_lineno: Option<usize>,
+    pub content: String,
+}
+
+/// A parameter of `function` that differs between the parent and the commit. A parameter
+/// missing on one side is represented by an empty string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamChange {
+    pub function: String,
+    pub old_param: String,
+    pub new_param: String,
+}
+
+/// Coarse purpose of a commit, see `CodeRepository::classify_commit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitCategory {
+    Bugfix,
+    Feature,
+    Refactor,
+    Docs,
+    Other,
+}
+
 #[derive(Error, Debug)]
 pub enum CodeRepositoryError {
     #[error("data store disconnected")]
     Open(#[from] git2::Error),
+    #[error("could not write extracted changes")]
+    Io(#[from] std::io::Error),
+    #[error("invalid pattern")]
+    Pattern(#[from] regex::Error),
+    #[error("{} does not exist in the parent commit", .0.display())]
+    NotFound(PathBuf),
+    #[error("{} does not exist in the commit", .0.display())]
+    NotInCommit(PathBuf),
+}
+
+/// Patterns tested together against a text, matching if any of them does. A `RegexSet`
+/// searches the text once for all patterns. A slice of `Regex` is kept as built, so flags set
+/// through `RegexBuilder` still apply, at the cost of one search per pattern.
+#[derive(Clone)]
+pub(crate) enum PatternSet {
+    Set(RegexSet),
+    Each(Vec<Regex>),
+}
+
+impl PatternSet {
+    pub(crate) fn new(patterns: &[Regex]) -> PatternSet {
+        PatternSet::Each(patterns.to_vec())
+    }
+
+    pub(crate) fn is_match(&self, text: &str) -> bool {
+        match self {
+            PatternSet::Set(set) => set.is_match(text),
+            PatternSet::Each(patterns) => patterns.iter().any(|p| p.is_match(text)),
+        }
+    }
+
+    /// Indices of the patterns matching `text`, in ascending order.
+    pub(crate) fn matches(&self, text: &str) -> Vec<usize> {
+        match self {
+            PatternSet::Set(set) => set.matches(text).into_iter().collect(),
+            PatternSet::Each(patterns) => patterns.iter().enumerate()
+                .filter(|(_, p)| p.is_match(text))
+                .map(|(i, _)| i)
+                .collect(),
+        }
+    }
 }
 
 pub struct CodeRepository {
     repo: Repository,
+    blob_cache: RefCell<BlobCache>,
+    sorting: Sort,
+}
+
+/// Changes collected before an extraction was stopped.
+#[derive(Debug)]
+pub struct PartialChanges {
+    pub changes: String,
+    pub truncated: bool,
+}
+
+/// A matching commit together with the message that caused the match and its changes.
+#[derive(Debug)]
+pub struct ExtractedCommit {
+    pub oid: Oid,
+    pub message: String,
+    pub changes: String,
+}
+
+/// Metadata of a commit; see `CodeRepository::commit_info`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitInfo {
+    pub author: String,
+    pub email: String,
+    /// Commit time in seconds since the Unix epoch.
+    pub time: i64,
+    pub summary: String,
+    pub message: String,
+}
+
+/// Size of a commit's diff; see `CodeRepository::commit_stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffStats {
+    pub files_changed: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+/// Changes of matching commits collected until a byte budget ran out.
+#[derive(Debug)]
+pub struct BoundedChanges {
+    pub changes: Vec<(Oid, String)>,
+    pub budget_exceeded: bool,
 }
 
 impl CodeRepository{
-    pub fn new(path: &str) -> Result<CodeRepository,CodeRepositoryError> {
-        Ok(CodeRepository{repo: Repository::open(path)?})
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<CodeRepository,CodeRepositoryError> {
+        Ok(CodeRepository::from_repo(Repository::open(path)?))
     }
 
-    pub fn contains_pattern(&self, oid: Oid, patterns: &[Regex]) -> bool {
-        let commit = self.repo.find_commit(oid).unwrap();
-        let commit_message = commit.message().unwrap();
-        patterns.iter().find(|p| p.find(&commit_message).is_some()).is_some()
+    /// Open the repository containing `path`, searching its parent directories like the git
+    /// command line does. `new` only opens a repository at exactly `path`.
+    pub fn discover<P: AsRef<Path>>(path: P) -> Result<CodeRepository,CodeRepositoryError> {
+        Ok(CodeRepository::from_repo(Repository::discover(path)?))
     }
 
-    pub fn commits_matching(&self, patterns: &[Regex]) -> Result<Vec<Oid>,CodeRepositoryError> {
-        let mut walk = self.repo.revwalk()?;
-        match walk.push_head() {
-            Ok(_) => {
-                Ok(walk
-                    .filter(|or| or.is_ok()).map(|or| or.unwrap())
-                    .filter(|oid| self.contains_pattern(*oid, patterns))
-                    .collect())
-            },
-            Err(_) => {
-                Ok(vec![])
-            }
-        }
+    /// Open a bare repository, e.g. a mirror clone without a working directory. `new` opens
+    /// bare repositories as well, but this fails for anything else.
+    pub fn open_bare<P: AsRef<Path>>(path: P) -> Result<CodeRepository,CodeRepositoryError> {
+        Ok(CodeRepository::from_repo(Repository::open_bare(path)?))
     }
 
-    fn get_diff_to_parent(&self, commit_id: Oid) -> git2::Diff {
-        let commit = self.repo.find_commit(commit_id).unwrap();
-        let commit_tree = commit.tree().unwrap();
-        let mut parents = commit.parents();
-        if parents.len() == 0 {
-            self.repo.diff_tree_to_tree(None, Some(&commit_tree), None).unwrap()
-        } else {
-            let parent = parents.next().unwrap();
-            let parent_tree = parent.tree().unwrap();
-            self.repo.diff_tree_to_tree(Some(&parent_tree), Some(&commit_tree), None).unwrap()
+    /// Wrap a repository that was already opened, e.g. with a custom odb or config.
+    pub fn from_repo(repo: Repository) -> CodeRepository {
+        CodeRepository{
+            repo,
+            blob_cache: RefCell::new(BlobCache::new(DEFAULT_BLOB_CACHE_CAPACITY)),
+            sorting: Sort::NONE,
         }
     }
 
-    pub fn get_changes(&self, commit_id: Oid) -> String {
-        let mut sum: Vec<String> = vec![];
-        let diff = self.get_diff_to_parent(commit_id);
-
-        let mut concat_lines = |_delta: DiffDelta, _maybe_hunk: Option<DiffHunk>, line: DiffLine| -> bool {
-            if line.origin_value() == git2::DiffLineType::Deletion {
-                sum.push(String::from_utf8_lossy(line.content()).to_string());
-            }
-            true
-        };
+    /// The underlying git2 repository, for queries this crate doesn't wrap.
+    pub fn repo(&self) -> &Repository {
+        &self.repo
+    }
 
+    /// Open the linked worktree `worktree_name` of the repository at `main_path`, so that
+    /// HEAD refers to the branch checked out in that worktree.
+    pub fn open_worktree<P: AsRef<Path>>(main_path: P, worktree_name: &str) -> Result<CodeRepository,CodeRepositoryError> {
+        let main_repo = Repository::open(main_path)?;
+        let worktree = main_repo.find_worktree(worktree_name)?;
+        Ok(CodeRepository::from_repo(Repository::open_from_worktree(&worktree)?))
+    }
 
-        diff.foreach(&mut |_,_| {true}, None, None, Some(&mut concat_lines)).unwrap();
-        sum.join("")
+    /// Order in which the commit queries walk the history, e.g. `Sort::TIME` for newest first
+    /// by commit time, optionally combined with `Sort::REVERSE`. Defaults to `Sort::NONE`.
+    pub fn with_sorting(self, sorting: Sort) -> CodeRepository {
+        CodeRepository{sorting, ..self}
     }
 
-    pub fn get_change_sets(&self, commit_id: Oid) -> Vec<ChangeSet> {
-        let diff = self.get_diff_to_parent(commit_id);
-        let mut changesets_by_path: HashMap<PathBuf, ChangeSet> = HashMap::new();
+    pub(crate) fn sorting(&self) -> Sort {
+        self.sorting
+    }
 
-        let mut add_change_set = |delta: DiffDelta, _maybe_hunk: Option<DiffHunk>, line: DiffLine| -> bool {
-
-            if line.origin_value() == git2::DiffLineType::Deletion {
-                match delta.old_file().path() {
-                    Some(old_file_path) => {
-                        let old_file_id = delta.old_file().id();
-                        let old_file_blob = self.repo.find_blob(old_file_id).unwrap();
-                        let old_file_content = String::from_utf8_lossy(old_file_blob.content());
-                        let file_change_set = changesets_by_path.entry(old_file_path.to_path_buf())
-                            .or_insert(ChangeSet::new(old_file_path, &old_file_content));
-                        file_change_set.add_line(line.old_lineno().unwrap() as usize - 1);
-                    },
-                    None => {},
-                }
-            }
+    pub(crate) fn revwalk(&self) -> Result<git2::Revwalk<'_>, CodeRepositoryError> {
+        let mut walk = self.repo.revwalk()?;
+        walk.set_sorting(self.sorting)?;
+        Ok(walk)
+    }
 
-            true
-        };
+    /// Replace the blob cache with an empty one holding at most `capacity` files.
+    pub fn with_blob_cache_capacity(self, capacity: usize) -> CodeRepository {
+        CodeRepository{blob_cache: RefCell::new(BlobCache::new(capacity)), ..self}
+    }
 
+    pub fn cache_stats(&self) -> CacheStats {
+        self.blob_cache.borrow().stats()
+    }
 
-        diff.foreach(&mut |_,_| {true}, None, None, Some(&mut add_change_set)).unwrap();
+    fn blob_content(&self, id: Oid) -> Result<Arc<String>, CodeRepositoryError> {
+        if let Some(content) = self.blob_cache.borrow_mut().get(id) {
+            return Ok(content);
+        }
+        let blob = self.repo.find_blob(id)?;
+        let content = Arc::new(String::from_utf8_lossy(blob.content()).to_string());
+        self.blob_cache.borrow_mut().insert(id, content.clone());
+        Ok(content)
+    }
 
-        changesets_by_path.drain().map(|(_, v)| v).collect()
+    /// Whether any pattern matches the commit message. A message that isn't valid UTF-8 is
+    /// converted lossily, so its ASCII parts can still match.
+    pub fn contains_pattern(&self, oid: Oid, patterns: &[Regex]) -> Result<bool, CodeRepositoryError> {
+        let commit_message = self.message(oid)?;
+        Ok(patterns.iter().any(|p| p.find(&commit_message).is_some()))
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::process;
-    use tempdir::TempDir;
-    use anyhow::Result;
-    use std::path::Path;
-    use git2::Repository;
-    use std::fs::File;
-    use std::io::prelude::*;
-    use indoc::indoc;
+    /// Byte ranges of every match of every pattern in the commit message, pattern by pattern.
+    /// The offsets refer to the message as returned in `CommitInfo::message`.
+    pub fn match_spans(&self, oid: Oid, patterns: &[Regex]) -> Result<Vec<Range<usize>>, CodeRepositoryError> {
+        let commit = self.repo.find_commit(oid)?;
+        let commit_message = String::from_utf8_lossy(commit.message_bytes());
+        Ok(patterns.iter()
+            .flat_map(|p| p.find_iter(&commit_message).map(|m| m.range()).collect::<Vec<_>>())
+            .collect())
+    }
 
-    fn with_empty_repo(test: fn(&Path) -> ()) -> Result<()>{
-        let repo_dir = TempDir::new("empty_repository")?;
-        let _repo = Repository::init(repo_dir.path())?;
+    /// The commit message, converted lossily if it isn't valid UTF-8.
+    fn message(&self, oid: Oid) -> Result<String, CodeRepositoryError> {
+        Ok(String::from_utf8_lossy(self.repo.find_commit(oid)?.message_bytes()).to_string())
+    }
 
-        test(repo_dir.path());
+    /// Like `contains_pattern`, with the patterns collected in a `PatternSet`.
+    pub(crate) fn contains_pattern_set(&self, oid: Oid, patterns: &PatternSet) -> Result<bool, CodeRepositoryError> {
+        Ok(patterns.is_match(&self.message(oid)?))
+    }
 
-        Ok(())
+    /// Like `contains_pattern`, but only the subject line of the message is searched, so
+    /// keywords in the body or trailers don't count.
+    pub fn contains_pattern_subject(&self, oid: Oid, patterns: &[Regex]) -> Result<bool, CodeRepositoryError> {
+        let commit_message = self.message(oid)?;
+        let subject = commit_message.split('\n').next().unwrap_or_default();
+        Ok(patterns.iter().any(|p| p.find(subject).is_some()))
     }
 
-    fn commit_file(repo_dir: &Path, filename: &str, content: &str, msg: &str) -> Result<()>{
-        let mut new_file = File::create(repo_dir.join(filename))?;
-        new_file.write(content.as_bytes())?;
+    /// Author, time and message of a commit. Fields that aren't valid UTF-8 are converted lossily.
+    pub fn commit_info(&self, oid: Oid) -> Result<CommitInfo, CodeRepositoryError> {
+        let commit = self.repo.find_commit(oid)?;
+        let author = commit.author();
+        Ok(CommitInfo{
+            author: String::from_utf8_lossy(author.name_bytes()).to_string(),
+            email: String::from_utf8_lossy(author.email_bytes()).to_string(),
+            time: commit.time().seconds(),
+            summary: String::from_utf8_lossy(commit.summary_bytes().unwrap_or_default()).to_string(),
+            message: String::from_utf8_lossy(commit.message_bytes()).to_string(),
+        })
+    }
 
-        process::Command::new("git")  
-            .args(&["add", filename])
-            .current_dir(repo_dir)
-            .output()?;
-        process::Command::new("git")  
-            .args(&["commit", "-a", "-m", msg])
-            .current_dir(repo_dir)
-            .output()?;
-        Ok(())
+    /// Number of files changed and lines inserted and deleted by the commit, diffed against its
+    /// first parent like `get_changes`.
+    pub fn commit_stats(&self, oid: Oid) -> Result<DiffStats, CodeRepositoryError> {
+        let stats = self.diff_for_commit(oid)?.stats()?;
+        Ok(DiffStats{
+            files_changed: stats.files_changed(),
+            insertions: stats.insertions(),
+            deletions: stats.deletions(),
+        })
     }
 
-    fn create_temporary_repository() -> Result<TempDir> {
-        let repo_dir = TempDir::new("buggy_repository")?;
-        Repository::init(repo_dir.path())?;
-        Ok(repo_dir)
+    /// Number of parents of the commit; 0 for a root commit and 2 or more for a merge.
+    pub fn parent_count(&self, oid: Oid) -> Result<usize, CodeRepositoryError> {
+        Ok(self.repo.find_commit(oid)?.parent_count())
     }
 
-    fn with_repo_containing_bugs(test: fn(&Path) -> ()) -> Result<()> {
-        let repo_dir = create_temporary_repository()?;
-        commit_file(repo_dir.path(), "foo", "", "Create foo")?;
+    /// Whether the commit has more than one parent, see `get_changes_per_parent`.
+    pub fn is_merge(&self, oid: Oid) -> Result<bool, CodeRepositoryError> {
+        Ok(self.parent_count(oid)? > 1)
+    }
 
-        test(repo_dir.path());
+    /// Whether every pattern matches the commit message; true for an empty slice.
+    pub fn contains_all_patterns(&self, oid: Oid, patterns: &[Regex]) -> Result<bool, CodeRepositoryError> {
+        let commit_message = self.message(oid)?;
+        Ok(patterns.iter().all(|p| p.is_match(&commit_message)))
+    }
 
-        Ok(())
+    /// Push HEAD onto `walk`. Returns false instead of an error if HEAD is an unborn branch,
+    /// i.e. the repository has no commits yet.
+    pub(crate) fn push_head(&self, walk: &mut git2::Revwalk) -> Result<bool,CodeRepositoryError> {
+        match walk.push_head() {
+            Ok(()) => Ok(true),
+            Err(push_error) => match self.repo.head() {
+                Err(head_error) if head_error.code() == git2::ErrorCode::UnbornBranch => Ok(false),
+                _ => Err(push_error.into()),
+            }
+        }
     }
 
-    fn with_repo_containing_function_pointer_bug(test: fn(&Path) -> ()) -> Result<()> {
-        let repo_dir = create_temporary_repository()?;
-        let buggy_code = indoc! {r#"
-            #include <stdio.h>
+    fn commits_where<F>(&self, predicate: F) -> Result<Vec<Oid>,CodeRepositoryError>
+        where F: Fn(Oid) -> Result<bool,CodeRepositoryError>
+    {
+        let mut walk = self.revwalk()?;
+        if !self.push_head(&mut walk)? {
+            return Ok(vec![]);
+        }
+        self.collect_where(walk, predicate)
+    }
 
-            void foo(int i) {
-              printf("%i\n", i);
+    /// The commits of `walk` accepted by `predicate`. Errors of the walk or the predicate end
+    /// the walk instead of being taken for a commit that doesn't match.
+    fn collect_where<F>(&self, walk: git2::Revwalk, predicate: F) -> Result<Vec<Oid>,CodeRepositoryError>
+        where F: Fn(Oid) -> Result<bool,CodeRepositoryError>
+    {
+        let mut accepted = vec![];
+        for oid in walk {
+            let oid = oid?;
+            if predicate(oid)? {
+                accepted.push(oid);
             }
+        }
+        Ok(accepted)
+    }
 
-            typedef void (*fpt)(unsigned int i);
+    pub fn commits_matching(&self, patterns: &[Regex]) -> Result<Vec<Oid>,CodeRepositoryError> {
+        self.scan().patterns(patterns).commits()
+    }
 
-            int main() {
-              fpt fp;
+    /// Like `commits_matching` with the patterns combined in a `RegexSet`, so every message is
+    /// searched once instead of once per pattern. Flags have to be set on the set itself, e.g.
+    /// through `RegexSetBuilder::case_insensitive`.
+    pub fn commits_matching_set(&self, patterns: &RegexSet) -> Result<Vec<Oid>,CodeRepositoryError> {
+        self.scan().pattern_set(patterns).commits()
+    }
 
-              fp = foo;
+    /// Number of commits `commits_matching` would return, without collecting them.
+    pub fn count_matching(&self, patterns: &[Regex]) -> Result<usize,CodeRepositoryError> {
+        self.scan().patterns(patterns).count()
+    }
 
-              foo(10);
-              fp(20);
-            }
-        "#};
+    /// Start a walk of the history filtered by several criteria at once, e.g.
+    /// `repository.scan().patterns(&patterns).since(time).limit(10).commits()`.
+    pub fn scan(&self) -> ScanBuilder<'_> {
+        ScanBuilder::new(self)
+    }
 
-        let fixed_bug = indoc!{r#"
-            #include <stdio.h>
+    /// Whether the commit changes a file matching any of `pathspecs`.
+    pub(crate) fn touches_paths(&self, oid: Oid, pathspecs: &[String]) -> Result<bool,CodeRepositoryError> {
+        let mut options = ExtractOptions::new();
+        for pathspec in pathspecs {
+            options.pathspec(pathspec);
+        }
+        Ok(self.get_diff_to_parent(oid, &options)?.deltas().len() > 0)
+    }
 
-            void foo(int i) {
-              printf("%i\n", i);
-            }
+    /// Parallel version of `commits_matching`, available with the `rayon` feature. The Oids
+    /// are collected from the revwalk first and then filtered on the rayon thread pool. As a
+    /// `Repository` is not `Sync`, every worker opens its own handle to the repository instead
+    /// of sharing `self`. The result is in the same order as for `commits_matching`.
+    #[cfg(feature = "rayon")]
+    pub fn commits_matching_par(&self, patterns: &[Regex]) -> Result<Vec<Oid>,CodeRepositoryError> {
+        use rayon::prelude::*;
+        let patterns = PatternSet::new(patterns);
+        let mut walk = self.revwalk()?;
+        if !self.push_head(&mut walk)? {
+            return Ok(vec![]);
+        }
+        let oids = walk.collect::<Result<Vec<Oid>,_>>()?;
+        let path = self.repo.path();
+        let matches: Vec<Option<Oid>> = oids
+            .into_par_iter()
+            .map_init(|| Repository::open(path), |repo, oid| {
+                let repo = repo.as_ref()
+                    .map_err(|error| git2::Error::new(error.code(), error.class(), error.message()))?;
+                let commit = repo.find_commit(oid)?;
+                Ok(if patterns.is_match(&String::from_utf8_lossy(commit.message_bytes())) { Some(oid) } else { None })
+            })
+            .collect::<Result<_,CodeRepositoryError>>()?;
+        Ok(matches.into_iter().flatten().collect())
+    }
 
-            typedef void (*fpt)(int i);
+    /// Like `commits_matching` for plain terms, which are matched literally and ignoring case,
+    /// so `"c++"` finds "Fix C++ build".
+    pub fn commits_matching_ci(&self, terms: &[&str]) -> Result<Vec<Oid>,CodeRepositoryError> {
+        let patterns = terms.iter()
+            .map(|term| Regex::new(&format!("(?i){}", regex::escape(term))))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.commits_matching(&patterns)
+    }
 
-            int main() {
-              fpt fp;
+    /// Lazy version of `commits_matching`: the history is walked only as far as the iterator
+    /// is consumed. Errors opening the walk are yielded as the first item.
+    pub fn commits_matching_iter<'a>(&'a self, patterns: &[Regex]) -> impl Iterator<Item = Result<Oid,CodeRepositoryError>> + 'a {
+        let walk_and_patterns = (|| {
+            let patterns = PatternSet::new(patterns);
+            let mut walk = self.revwalk()?;
+            let has_head = self.push_head(&mut walk)?;
+            Ok((walk, patterns, has_head))
+        })();
+        let (walk, setup_error) = match walk_and_patterns {
+            Ok((walk, patterns, true)) => (Some((walk, patterns)), None),
+            Ok(_) => (None, None),
+            Err(error) => (None, Some(error)),
+        };
+        setup_error.map(Err).into_iter().chain(walk.into_iter().flat_map(move |(walk, patterns)| {
+            walk.filter_map(move |oid| {
+                let matched = oid.map_err(CodeRepositoryError::from)
+                    .and_then(|oid| Ok(self.contains_pattern_set(oid, &patterns)?.then_some(oid)));
+                matched.transpose()
+            })
+        }))
+    }
 
-              fp = foo;
+    /// Like `commits_matching`, but only follows the first parent of merge commits, as
+    /// `git log --first-parent` does, so commits made on merged branches are left out.
+    pub fn commits_matching_first_parent(&self, patterns: &[Regex]) -> Result<Vec<Oid>,CodeRepositoryError> {
+        let patterns = PatternSet::new(patterns);
+        let mut walk = self.revwalk()?;
+        if !self.push_head(&mut walk)? {
+            return Ok(vec![]);
+        }
+        walk.set_sorting(self.sorting | Sort::TOPOLOGICAL)?;
+        walk.simplify_first_parent()?;
+        self.collect_where(walk, |oid| self.contains_pattern_set(oid, &patterns))
+    }
 
-              foo(10);
-              fp(20);
-            }
-        "#};
+    /// The first `max` commits `commits_matching` would return. The walk ends as soon as they
+    /// are found.
+    pub fn commits_matching_limited(&self, patterns: &[Regex], max: usize) -> Result<Vec<Oid>,CodeRepositoryError> {
+        if max == 0 {
+            return Ok(vec![]);
+        }
+        self.commits_matching_iter(patterns).take(max).collect()
+    }
 
-        commit_file(repo_dir.path(), "main.c", buggy_code, "this should work!")?;
-        commit_file(repo_dir.path(), "main.c", fixed_bug, "fixed bug")?;
+    /// Like `commits_matching`, but walks the history of the revision `start` (a branch, tag,
+    /// commit id, ...) instead of HEAD.
+    pub fn commits_matching_from(&self, start: &str, patterns: &[Regex]) -> Result<Vec<Oid>,CodeRepositoryError> {
+        let patterns = PatternSet::new(patterns);
+        let start_commit = self.repo.revparse_single(start)?.peel_to_commit()?;
+        let mut walk = self.revwalk()?;
+        walk.push(start_commit.id())?;
+        self.collect_where(walk, |oid| self.contains_pattern_set(oid, &patterns))
+    }
 
-        test(repo_dir.path());
+    /// Like `commits_matching`, but only the subject line of each message is searched.
+    pub fn commits_matching_subject(&self, patterns: &[Regex]) -> Result<Vec<Oid>,CodeRepositoryError> {
+        self.commits_where(|oid| self.contains_pattern_subject(oid, patterns))
+    }
 
-        Ok(())
+    /// Like `commits_matching`, restricted to the commits reachable from `to` but not from
+    /// `from`, as in `git log from..to`. Both are revisions like branches, tags or commit ids.
+    pub fn commits_matching_range(&self, from: &str, to: &str, patterns: &[Regex]) -> Result<Vec<Oid>,CodeRepositoryError> {
+        let patterns = PatternSet::new(patterns);
+        let mut walk = self.revwalk()?;
+        walk.push(self.repo.revparse_single(to)?.peel_to_commit()?.id())?;
+        walk.hide(self.repo.revparse_single(from)?.peel_to_commit()?.id())?;
+        self.collect_where(walk, |oid| self.contains_pattern_set(oid, &patterns))
+    }
 
+    /// Like `commits_matching`, but a commit has to match every pattern instead of any.
+    pub fn commits_matching_all(&self, patterns: &[Regex]) -> Result<Vec<Oid>,CodeRepositoryError> {
+        self.commits_where(|oid| self.contains_all_patterns(oid, patterns))
     }
 
-    #[test]
-    fn open_repository() -> Result<()> {
-        with_empty_repo(|repo_path: &Path| {
-            let repository = CodeRepository::new(repo_path.to_str().unwrap());
-            assert!(repository.is_ok());
-        })
+    /// Like `commits_matching`, with every commit paired with the indices into `patterns` of
+    /// those matching its message, in ascending order.
+    pub fn commits_matching_labeled(&self, patterns: &[Regex]) -> Result<Vec<(Oid, Vec<usize>)>,CodeRepositoryError> {
+        self.commits_labeled(&PatternSet::new(patterns))
     }
 
-    #[test]
-    fn find_no_commits_on_empty_repository() -> Result<()> {
-        with_empty_repo(|repo_path: &Path| {
-            let empty_repo = CodeRepository::new(repo_path.to_str().unwrap());
-            let patterns: Vec<Regex> = vec![];
-            assert_eq!(empty_repo.unwrap().commits_matching(&patterns).unwrap().len(), 0);
-        })
+    /// Like `commits_matching_labeled`, with the indices of the matching patterns of the set
+    /// found in a single search of every message.
+    pub fn commits_matching_labeled_set(&self, patterns: &RegexSet) -> Result<Vec<(Oid, Vec<usize>)>,CodeRepositoryError> {
+        self.commits_labeled(&PatternSet::Set(patterns.clone()))
     }
 
-    fn number_of_commits_in_this_repo() -> usize {
-        let stdout = process::Command::new("git")
-            .args(&["rev-list", "--all", "--count"])
-            .output()
-            .expect("working git command").stdout;
-        String::from_utf8_lossy(&stdout).trim().parse().unwrap()
+    fn commits_labeled(&self, patterns: &PatternSet) -> Result<Vec<(Oid, Vec<usize>)>,CodeRepositoryError> {
+        let mut walk = self.revwalk()?;
+        if !self.push_head(&mut walk)? {
+            return Ok(vec![]);
+        }
+        let mut labeled = vec![];
+        for oid in walk {
+            let oid = oid?;
+            let matched = patterns.matches(&self.message(oid)?);
+            if !matched.is_empty() {
+                labeled.push((oid, matched));
+            }
+        }
+        Ok(labeled)
     }
 
-    #[test]
-    fn find_all_commits_on_this_repo_with_matchall_pattern() {
-        let some_repo = CodeRepository::new(".").unwrap();
-        let patterns = vec![Regex::new(".*").unwrap()];
-        assert_eq!(some_repo.commits_matching(&patterns).unwrap().len(), number_of_commits_in_this_repo());
+    /// Commits whose diff adds or removes, depending on `line_kind`, a line matched by any of
+    /// `patterns`, regardless of the message. Diffing every commit makes this much slower than
+    /// `commits_matching`.
+    pub fn commits_changing(&self, patterns: &[Regex], line_kind: LineKind) -> Result<Vec<Oid>,CodeRepositoryError> {
+        let patterns = PatternSet::new(patterns);
+        let mut walk = self.revwalk()?;
+        if !self.push_head(&mut walk)? {
+            return Ok(vec![]);
+        }
+        self.collect_where(walk, |oid| self.changes_contain_pattern_set(oid, &patterns, line_kind))
     }
 
-    #[test]
-    fn commits_are_filtered_with_patterns() {
-        let some_repo = CodeRepository::new(".").unwrap();
-        let patterns = vec![Regex::new("Initial").unwrap()];
-        assert!(some_repo.commits_matching(&patterns).unwrap().len() < number_of_commits_in_this_repo());
+    fn changes_contain_pattern_set(&self, oid: Oid, patterns: &PatternSet, line_kind: LineKind) -> Result<bool,CodeRepositoryError> {
+        let mut found = false;
+        self.get_changes_for_each(oid, |_, kind, content| {
+            let wanted = matches!((line_kind, kind),
+                (LineKind::Deleted | LineKind::Both, DiffLineType::Deletion) | (LineKind::Added | LineKind::Both, DiffLineType::Addition));
+            found = wanted && patterns.is_match(content);
+            !found
+        })?;
+        Ok(found)
     }
 
-    fn initial_commit(repo_path: &Path) -> String {
-        let stdout = process::Command::new("git")
-            .args(&["rev-list", "--max-parents=0", "HEAD"])
-            .current_dir(repo_path)
-            .output()
-            .expect("querying first commit id")
-            .stdout;
-        String::from_utf8_lossy(&stdout).trim().to_string()
+    /// Whether `author` matches the commit's author as `"Name <email>"`.
+    pub(crate) fn author_matches(&self, oid: Oid, author: &Regex) -> Result<bool, CodeRepositoryError> {
+        let commit = self.repo.find_commit(oid)?;
+        let signature = commit.author();
+        let name_and_email = format!("{} <{}>",
+            String::from_utf8_lossy(signature.name_bytes()),
+            String::from_utf8_lossy(signature.email_bytes()));
+        Ok(author.is_match(&name_and_email))
     }
 
-    #[test]
-    fn extract_empty_string_from_initial_commit_adding_empty_file() -> Result<()> {
-        with_repo_containing_bugs(|path: &Path| {
-            let some_repo = CodeRepository::new(path.to_str().unwrap()).unwrap();
-            let commit_id = initial_commit(path);
-            dbg!(&commit_id);
-            let commit = git2::Oid::from_str(&commit_id).unwrap();
-            let changes: String = some_repo.get_changes(commit);
-            assert!(changes.is_empty());
+    /// Like `commits_matching`, restricted to commits whose author matches `author`. The regex
+    /// is tested against `"Name <email>"`, so it can match either part.
+    pub fn commits_matching_by_author(&self, patterns: &[Regex], author: &Regex) -> Result<Vec<Oid>,CodeRepositoryError> {
+        let patterns = PatternSet::new(patterns);
+        self.commits_where(|oid| Ok(self.contains_pattern_set(oid, &patterns)? && self.author_matches(oid, author)?))
+    }
+
+    /// Like `commits_matching`, restricted to commits whose commit time lies within
+    /// `since..=until`, both given in seconds since the Unix epoch.
+    pub fn commits_matching_between(&self, patterns: &[Regex], since: i64, until: i64) -> Result<Vec<Oid>,CodeRepositoryError> {
+        let patterns = PatternSet::new(patterns);
+        self.commits_where(|oid| {
+            let time = self.repo.find_commit(oid)?.time().seconds();
+            Ok((since..=until).contains(&time) && self.contains_pattern_set(oid, &patterns)?)
         })
     }
-    
-    #[test]
-    fn extract_empty_string_from_initial_commit_adding_nonempty_file() {
-        let some_repo = CodeRepository::new(".").unwrap();
-        let commit = git2::Oid::from_str("a26f0fcc8faea89939859ebba4e51265ba415db0").unwrap();
-        let changes: String = some_repo.get_changes(commit);
-        dbg!(&changes);
-        assert!(changes.is_empty());
+
+    /// Commits matching any of the `include` patterns but none of the `exclude` patterns.
+    pub fn commits_matching_filtered(&self, include: &[Regex], exclude: &[Regex]) -> Result<Vec<Oid>,CodeRepositoryError> {
+        let include = PatternSet::new(include);
+        let exclude = PatternSet::new(exclude);
+        self.commits_where(|oid| Ok(self.contains_pattern_set(oid, &include)? && !self.contains_pattern_set(oid, &exclude)?))
     }
 
-    fn get_last_commit(repo_path: &str) -> String {
-        let stdout = process::Command::new("git")
-            .args(&["rev-list", "--max-count=1", "master"])
-            .current_dir(repo_path)
-            .output()
-            .expect("querying the last commit on master")
-            .stdout;
-        String::from_utf8_lossy(&stdout).trim().to_string()
+    /// Changes of the commits among the `count` most recent ones on HEAD whose message matches.
+    pub fn recent_changes(&self, count: usize, patterns: &[Regex]) -> Result<Vec<(Oid, String)>, CodeRepositoryError> {
+        let patterns = PatternSet::new(patterns);
+        let mut walk = self.revwalk()?;
+        if !self.push_head(&mut walk)? {
+            return Ok(vec![]);
+        }
+
+        let mut changes = vec![];
+        for oid in walk.take(count) {
+            let oid = oid?;
+            if self.contains_pattern_set(oid, &patterns)? {
+                changes.push((oid, self.get_changes_with(oid, &ExtractOptions::default())?));
+            }
+        }
+        Ok(changes)
     }
 
-    #[test]
-    fn extract_string_from_commit() -> Result<()>{
-        with_repo_containing_function_pointer_bug(|project_path| {
-            let project_path_str = project_path.to_str().unwrap();
-            let some_repo = CodeRepository::new(project_path_str).unwrap();
-            let commit = git2::Oid::from_str(&get_last_commit(project_path_str)).unwrap();
-            let changes: String = some_repo.get_changes(commit);
-            dbg!(&changes);
-            assert!(changes.contains("typedef"));
-            assert!(!changes.contains("fp(20)"));
-        })?;
-        Ok(())
+    /// Every matching commit with its changes as `get_changes` extracts them, walked lazily
+    /// like `commits_matching_iter`. A commit whose changes can't be extracted yields an error
+    /// without ending the iteration.
+    pub fn matching_changes<'a>(&'a self, patterns: &[Regex]) -> impl Iterator<Item = Result<(Oid, String),CodeRepositoryError>> + 'a {
+        self.commits_matching_iter(patterns).map(move |oid| {
+            let oid = oid?;
+            Ok((oid, self.get_changes(oid)?))
+        })
     }
 
-    #[test]
-    fn extract_line_from_commit() -> Result<()> {
-        with_repo_containing_function_pointer_bug(|project_path| {
-            let prj_str = project_path.to_str().unwrap();
-            let some_repo = CodeRepository::new(prj_str).unwrap();
-            let commit = git2::Oid::from_str(&get_last_commit(prj_str)).unwrap();
-            let changes: String = some_repo.get_changes(commit);
-            dbg!(&changes);
-            assert!(changes.contains("typedef"));
-            assert!(!changes.contains("main"));
-        })?;
-        Ok(())
+    /// Like `commits_matching`, but returns each commit's message and extracted changes.
+    pub fn matching_changes_full(&self, patterns: &[Regex]) -> Result<Vec<ExtractedCommit>, CodeRepositoryError> {
+        self.commits_matching(patterns)?.into_iter().map(|oid| {
+            let commit = self.repo.find_commit(oid)?;
+            Ok(ExtractedCommit{
+                oid,
+                message: String::from_utf8_lossy(commit.message_bytes()).to_string(),
+                changes: self.get_changes_with(oid, &ExtractOptions::default())?,
+            })
+        }).collect()
     }
 
-    #[test]
-    #[ignore]
-    fn extract_line_from_commit_to_changeset() -> Result<()> {
-        with_repo_containing_function_pointer_bug(|project_path| {
-            let prj_str = project_path.to_str().unwrap();
-            let some_repo = CodeRepository::new(prj_str).unwrap();
+    /// Changes of matching commits as long as their total size stays within `max_total_bytes`.
+    /// The commit whose changes would exceed the budget is left out and ends the extraction.
+    pub fn matching_changes_bounded(&self, patterns: &[Regex], max_total_bytes: usize) -> Result<BoundedChanges, CodeRepositoryError> {
+        let mut changes = vec![];
+        let mut total_bytes = 0;
+        for oid in self.commits_matching(patterns)? {
+            let commit_changes = self.get_changes_with(oid, &ExtractOptions::default())?;
+            total_bytes += commit_changes.len();
+            if total_bytes > max_total_bytes {
+                return Ok(BoundedChanges{changes, budget_exceeded: true});
+            }
+            changes.push((oid, commit_changes));
+        }
+        Ok(BoundedChanges{changes, budget_exceeded: false})
+    }
+
+    /// Write the changes of every matching commit to `out_dir/<short-oid>.txt`, overwriting
+    /// existing files, and return the number of files written.
+    pub fn dump_changes(&self, patterns: &[Regex], out_dir: &Path) -> Result<usize, CodeRepositoryError> {
+        std::fs::create_dir_all(out_dir)?;
+        let matching = self.commits_matching(patterns)?;
+        for oid in &matching {
+            let short_id = self.repo.find_commit(*oid)?.as_object().short_id()?;
+            let filename = format!("{}.txt", short_id.as_str().unwrap_or_default());
+            std::fs::write(out_dir.join(filename), self.get_changes_with(*oid, &ExtractOptions::default())?)?;
+        }
+        Ok(matching.len())
+    }
+
+    fn diff_options(options: &ExtractOptions) -> DiffOptions {
+        let mut diff_options = DiffOptions::new();
+        // Included typechanges are diffed as a deletion plus an addition, so their content shows up.
+        diff_options.include_typechange(!options.include_typechange);
+        if let Some(lines) = options.context_lines {
+            diff_options.context_lines(lines);
+        }
+        if let Some(lines) = options.interhunk_lines {
+            diff_options.interhunk_lines(lines);
+        }
+        diff_options.force_text(options.include_binary);
+        diff_options.ignore_whitespace(options.ignore_whitespace);
+        diff_options.ignore_whitespace_eol(options.ignore_whitespace);
+        for pathspec in &options.pathspecs {
+            diff_options.pathspec(pathspec);
+        }
+        diff_options
+    }
+
+    fn get_diff_to_parent(&self, commit_id: Oid, options: &ExtractOptions) -> Result<git2::Diff<'_>, CodeRepositoryError> {
+        let mut diff = self.diff_to_parent_with(commit_id, &mut CodeRepository::diff_options(options))?;
+        if options.detect_renames {
+            diff.find_similar(Some(DiffFindOptions::new().renames(true)))?;
+        }
+        Ok(diff)
+    }
+
+    /// Diff of the commit against its first parent with the default `ExtractOptions`.
+    fn diff_for_commit(&self, commit_id: Oid) -> Result<git2::Diff<'_>, CodeRepositoryError> {
+        self.get_diff_to_parent(commit_id, &ExtractOptions::default())
+    }
+
+    fn diff_to_parent_with(&self, commit_id: Oid, diff_options: &mut DiffOptions) -> Result<git2::Diff<'_>, CodeRepositoryError> {
+        self.diff_to_nth_parent_with(commit_id, 0, diff_options)
+    }
+
+    /// Diff of the commit against its `parent_index`-th parent, or against the empty tree for a
+    /// root commit.
+    fn diff_to_nth_parent_with(&self, commit_id: Oid, parent_index: usize, diff_options: &mut DiffOptions) -> Result<git2::Diff<'_>, CodeRepositoryError> {
+        let commit = self.repo.find_commit(commit_id)?;
+        let commit_tree = commit.tree()?;
+        let parent_tree = match commit.parent_count() {
+            0 => None,
+            _ => Some(commit.parent(parent_index)?.tree()?),
+        };
+        Ok(self.repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit_tree), Some(diff_options))?)
+    }
+
+    /// Coarse category of a commit. Conventional-commit prefixes (`fix:`, `feat:`, `refactor:`,
+    /// `docs:`) decide first; otherwise commits touching only documentation are `Docs` and
+    /// commits changing only whitespace are `Refactor`.
+    pub fn classify_commit(&self, commit_id: Oid) -> Result<CommitCategory, CodeRepositoryError> {
+        let commit = self.repo.find_commit(commit_id)?;
+        let summary = String::from_utf8_lossy(commit.summary_bytes().unwrap_or_default()).to_lowercase();
+        if let Some((prefix, _)) = summary.split_once(':') {
+            let commit_type = prefix.split('(').next().unwrap_or_default().trim_end_matches('!');
+            match commit_type.trim() {
+                "fix" => return Ok(CommitCategory::Bugfix),
+                "feat" => return Ok(CommitCategory::Feature),
+                "refactor" => return Ok(CommitCategory::Refactor),
+                "docs" => return Ok(CommitCategory::Docs),
+                _ => {},
+            }
+        }
+
+        let statuses = self.file_statuses(commit_id)?;
+        if !statuses.is_empty() && statuses.iter().all(|(path, _)| is_documentation(path)) {
+            return Ok(CommitCategory::Docs);
+        }
+
+        let all_changes = self.diff_for_commit(commit_id)?.stats()?;
+        let mut ignoring_whitespace = DiffOptions::new();
+        ignoring_whitespace.ignore_whitespace(true);
+        let non_whitespace_changes = self.diff_to_parent_with(commit_id, &mut ignoring_whitespace)?.stats()?;
+        let changed_lines = |stats: &git2::DiffStats| stats.insertions() + stats.deletions();
+        if changed_lines(&all_changes) > 0 && changed_lines(&non_whitespace_changes) == 0 {
+            return Ok(CommitCategory::Refactor);
+        }
+        Ok(CommitCategory::Other)
+    }
+
+    /// Paths of the diff's files that look generated by name or by content on either side.
+    fn generated_paths(&self, diff: &git2::Diff) -> Result<HashSet<PathBuf>, CodeRepositoryError> {
+        let mut generated = HashSet::new();
+        for delta in diff.deltas() {
+            // Typechanged and binary files are never extracted, so their content isn't judged.
+            if delta.status() == Delta::Typechange || delta.flags().is_binary() {
+                continue;
+            }
+            let files = [delta.old_file(), delta.new_file()];
+            let mut is_generated = files.iter().any(|file| file.path().is_some_and(has_generated_name));
+            for file in files.iter().filter(|file| !file.id().is_zero()) {
+                is_generated = is_generated || self.blob_looks_generated(file.id())?;
+            }
+            if is_generated {
+                generated.extend(delta.old_file().path().map(Path::to_path_buf));
+                generated.extend(delta.new_file().path().map(Path::to_path_buf));
+            }
+        }
+        Ok(generated)
+    }
+
+    /// Whether the start of a text blob `looks_generated`. The blob bypasses the blob cache
+    /// and binary blobs are never decoded.
+    fn blob_looks_generated(&self, id: Oid) -> Result<bool, CodeRepositoryError> {
+        let blob = self.repo.find_blob(id)?;
+        if blob.is_binary() {
+            return Ok(false);
+        }
+        let sample = &blob.content()[..blob.size().min(GENERATED_SAMPLE_SIZE)];
+        Ok(looks_generated(&String::from_utf8_lossy(sample)))
+    }
+
+    fn concat_changes<F>(&self, commit_id: Oid, options: &ExtractOptions, keep_delta: F) -> Result<String, CodeRepositoryError>
+        where F: Fn(&DiffDelta) -> bool
+    {
+        let diff = self.get_diff_to_parent(commit_id, options)?;
+        self.concat_diff_changes(&diff, options, keep_delta)
+    }
+
+    fn concat_diff_changes<F>(&self, diff: &git2::Diff, options: &ExtractOptions, keep_delta: F) -> Result<String, CodeRepositoryError>
+        where F: Fn(&DiffDelta) -> bool
+    {
+        Ok(self.diff_changes_by_file(diff, options, keep_delta)?.into_iter().map(|(_, changes)| changes).collect())
+    }
+
+    /// The extracted lines of every file with at least one of them, in diff order.
+    fn diff_changes_by_file<F>(&self, diff: &git2::Diff, options: &ExtractOptions, keep_delta: F) -> Result<Vec<(PathBuf, String)>, CodeRepositoryError>
+        where F: Fn(&DiffDelta) -> bool
+    {
+        use git2::DiffLineType::*;
+
+        let mut files: Vec<(PathBuf, String)> = vec![];
+        let (deleted, added) = match options.line_kind {
+            LineKind::Deleted => (true, false),
+            LineKind::Added => (false, true),
+            LineKind::Both => (true, true),
+        };
+
+        self.for_each_extracted_line(diff, options, |delta, line| {
+            let wanted = match line.origin_value() {
+                Deletion => deleted,
+                Addition => added,
+                DeleteEOFNL => deleted && options.eof_newline == EofNewline::Preserve,
+                AddEOFNL => added && options.eof_newline == EofNewline::Preserve,
+                Context => options.include_context,
+                ContextEOFNL => options.eof_newline == EofNewline::Preserve,
+                _ => false,
+            };
+            let path = delta.old_file().path().or_else(|| delta.new_file().path());
+            if let Some(path) = path.filter(|_| wanted && keep_delta(delta)) {
+                if files.last().map(|(last, _)| last.as_path()) != Some(path) {
+                    files.push((path.to_path_buf(), String::new()));
+                }
+                let changes = &mut files.last_mut().unwrap().1;
+                if let (LineKind::Both, Deletion | Addition | Context) = (options.line_kind, line.origin_value()) {
+                    changes.push(line.origin());
+                }
+                changes.push_str(&String::from_utf8_lossy(line.content()));
+            }
+            true
+        })?;
+        Ok(files)
+    }
+
+    /// Hand every line of `diff` to `f`, except for those of generated and typechanged files
+    /// and of binary files unless `options` include them. This is the one place deciding which
+    /// files are extracted. The walk only stops early if `f` returns false, which is not an
+    /// error.
+    fn for_each_extracted_line<F>(&self, diff: &git2::Diff, options: &ExtractOptions, mut f: F) -> Result<(), CodeRepositoryError>
+        where F: FnMut(&DiffDelta, &DiffLine) -> bool
+    {
+        let generated = self.generated_paths(diff)?;
+        let mut stopped = false;
+
+        let mut visit_line = |delta: DiffDelta, _maybe_hunk: Option<DiffHunk>, line: DiffLine| -> bool {
+            let is_generated = [delta.old_file().path(), delta.new_file().path()].iter()
+                .any(|path| path.is_some_and(|p| generated.contains(p)));
+            let is_skipped = is_generated || delta.status() == Delta::Typechange || (delta.flags().is_binary() && !options.include_binary);
+            if !is_skipped && !f(&delta, &line) {
+                stopped = true;
+                return false;
+            }
+            true
+        };
+
+        match diff.foreach(&mut |_,_| {true}, None, None, Some(&mut visit_line)) {
+            Err(e) if stopped && e.code() == git2::ErrorCode::User => Ok(()),
+            result => Ok(result?),
+        }
+    }
+
+    /// Code removed by the commit. Merge commits are diffed against their first parent only,
+    /// see `get_changes_per_parent` for the other parents.
+    pub fn get_changes(&self, commit_id: Oid) -> Result<String, CodeRepositoryError> {
+        let mut changes = String::new();
+        self.get_changes_for_each(commit_id, |_, kind, content| {
+            if kind == DiffLineType::Deletion {
+                changes.push_str(content);
+            }
+            true
+        })?;
+        Ok(changes)
+    }
+
+    /// Hand every line of the commit's diff to `f` together with the path of its file and its
+    /// kind, without collecting the changes first. Generated, binary and typechanged files are
+    /// skipped as in `get_changes`. The walk only stops early if `f` returns false, which is
+    /// not an error.
+    pub fn get_changes_for_each<F>(&self, commit_id: Oid, f: F) -> Result<(), CodeRepositoryError>
+        where F: FnMut(&Path, DiffLineType, &str) -> bool
+    {
+        self.get_changes_for_each_with(commit_id, &ExtractOptions::default(), f)
+    }
+
+    /// Like `get_changes_for_each` for the diff described by `options`. Every line of the
+    /// extracted files is handed to `f`; `line_kind`, `include_context` and `eof_newline` are
+    /// left to `f` to apply.
+    pub fn get_changes_for_each_with<F>(&self, commit_id: Oid, options: &ExtractOptions, mut f: F) -> Result<(), CodeRepositoryError>
+        where F: FnMut(&Path, DiffLineType, &str) -> bool
+    {
+        let diff = self.get_diff_to_parent(commit_id, options)?;
+        self.for_each_extracted_line(&diff, options, |delta, line| {
+            match delta.old_file().path().or_else(|| delta.new_file().path()) {
+                Some(path) => f(path, line.origin_value(), &String::from_utf8_lossy(line.content())),
+                None => true,
+            }
+        })
+    }
+
+    /// Like `get_changes`, keyed by the path of the file each line was removed from.
+    pub fn get_changes_by_file(&self, commit_id: Oid) -> Result<HashMap<PathBuf, String>, CodeRepositoryError> {
+        let options = ExtractOptions::default();
+        let diff = self.get_diff_to_parent(commit_id, &options)?;
+        Ok(self.diff_changes_by_file(&diff, &options, |_| true)?.into_iter().collect())
+    }
+
+    /// Like `get_changes`, with one entry per parent of the commit in parent order. A root commit
+    /// has a single entry diffed against the empty tree.
+    pub fn get_changes_per_parent(&self, commit_id: Oid) -> Result<Vec<String>, CodeRepositoryError> {
+        let options = ExtractOptions::default();
+        let parent_count = self.repo.find_commit(commit_id)?.parent_count().max(1);
+        (0..parent_count).map(|parent_index| {
+            let diff = self.diff_to_nth_parent_with(commit_id, parent_index, &mut CodeRepository::diff_options(&options))?;
+            self.concat_diff_changes(&diff, &options, |_| true)
+        }).collect()
+    }
+
+    pub fn get_changes_with(&self, commit_id: Oid, options: &ExtractOptions) -> Result<String, CodeRepositoryError> {
+        self.concat_changes(commit_id, options, |_| true)
+    }
+
+    /// Like `get_changes`, but `keep_going` is asked before each line is collected; returning
+    /// false aborts the diff traversal and marks the result as truncated.
+    pub fn get_changes_while<F>(&self, commit_id: Oid, mut keep_going: F) -> Result<PartialChanges, CodeRepositoryError>
+        where F: FnMut(&str) -> bool
+    {
+        let mut changes = String::new();
+        let mut truncated = false;
+        self.get_changes_for_each(commit_id, |_, kind, content| {
+            if kind != DiffLineType::Deletion {
+                return true;
+            }
+            truncated = !keep_going(content);
+            if !truncated {
+                changes.push_str(content);
+            }
+            !truncated
+        })?;
+        Ok(PartialChanges{changes, truncated})
+    }
+
+    /// Like `get_changes`, restricted to files whose extension maps to `language`.
+    pub fn get_changes_for_language(&self, commit_id: Oid, language: Language) -> Result<String, CodeRepositoryError> {
+        self.concat_changes(commit_id, &ExtractOptions::default(), |delta| {
+            delta.old_file().path().and_then(Language::from_path) == Some(language)
+        })
+    }
+
+    /// One change set per file the commit changed. Its `code` and `lines` refer to the file
+    /// before the commit with the lines removed from it, except for an added file, which has
+    /// no such lines and refers to the new file with the lines added to it. The `filename` is
+    /// always the path after the commit, so for a renamed file `code` and `lines` describe the
+    /// old file at `ChangeStatus::Renamed::old_path`, not the file at `filename`.
+    pub fn get_change_sets(&self, commit_id: Oid) -> Result<Vec<ChangeSet>, CodeRepositoryError> {
+        self.get_change_sets_with(commit_id, &ExtractOptions::default())
+    }
+
+    fn change_status(delta: &DiffDelta) -> ChangeStatus {
+        match delta.status() {
+            Delta::Added => ChangeStatus::Added,
+            Delta::Deleted => ChangeStatus::Deleted,
+            Delta::Renamed => ChangeStatus::Renamed{
+                old_path: delta.old_file().path().unwrap_or_else(|| Path::new("")).to_path_buf(),
+            },
+            _ => ChangeStatus::Modified,
+        }
+    }
+
+    /// Like `get_change_sets`, with the diff shaped by `options`: its pathspecs, whitespace
+    /// handling, rename detection and the inclusion of binary and typechanged files decide which
+    /// files and lines get a change set. The line selection options `line_kind`,
+    /// `include_context` and `eof_newline` are ignored, since a change set always marks the
+    /// removed lines, or the added lines of an added file. Context and interhunk lines only
+    /// shape the hunks and do not change which lines are marked.
+    pub fn get_change_sets_with(&self, commit_id: Oid, options: &ExtractOptions) -> Result<Vec<ChangeSet>, CodeRepositoryError> {
+        let diff = self.get_diff_to_parent(commit_id, options)?;
+        let mut changesets_by_path: HashMap<PathBuf, ChangeSet> = HashMap::new();
+        let mut blob_error = None;
+
+        self.for_each_extracted_line(&diff, options, |delta, line| {
+            let (file, lineno) = match (delta.status(), line.origin_value()) {
+                (Delta::Added, DiffLineType::Addition) => (delta.new_file(), line.new_lineno()),
+                (_, DiffLineType::Deletion) => (delta.old_file(), line.old_lineno()),
+                _ => return true,
+            };
+            // Added and deleted lines always have a line number in their file.
+            if let (Some(path), Some(lineno)) = (file.path(), lineno) {
+                let file_change_set = match changesets_by_path.entry(path.to_path_buf()) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        let content = match self.blob_content(file.id()) {
+                            Ok(content) => content,
+                            Err(error) => {
+                                blob_error = Some(error);
+                                return false;
+                            }
+                        };
+                        let status = CodeRepository::change_status(delta);
+                        let filename = match status {
+                            ChangeStatus::Renamed{..} => delta.new_file().path().unwrap_or(path),
+                            _ => path,
+                        };
+                        entry.insert(ChangeSet::new(filename, &content).with_status(status))
+                    },
+                };
+                file_change_set.add_line(lineno as usize - 1);
+            }
+            true
+        })?;
+        if let Some(error) = blob_error {
+            return Err(error);
+        }
+
+        Ok(changesets_by_path.drain().map(|(_, v)| v).collect())
+    }
+
+    /// Path and kind of change of every file touched by the commit.
+    pub fn file_statuses(&self, commit_id: Oid) -> Result<Vec<(PathBuf, Delta)>, CodeRepositoryError> {
+        let diff = self.diff_for_commit(commit_id)?;
+        Ok(diff.deltas()
+            .filter_map(|delta| {
+                let path = delta.new_file().path().or_else(|| delta.old_file().path())?;
+                Some((path.to_path_buf(), delta.status()))
+            })
+            .collect())
+    }
+
+    /// Every added, deleted and context line of the commit's diff in diff order.
+    pub fn annotated_lines(&self, commit_id: Oid) -> Result<Vec<AnnotatedLine>, CodeRepositoryError> {
+        let diff = self.diff_for_commit(commit_id)?;
+        let mut lines = vec![];
+
+        let mut annotate = |delta: DiffDelta, _maybe_hunk: Option<DiffHunk>, line: DiffLine| -> bool {
+            let path = delta.new_file().path().or_else(|| delta.old_file().path());
+            if let (Some(path), '+' | '-' | ' ') = (path, line.origin()) {
+                lines.push(AnnotatedLine{
+                    path: path.to_path_buf(),
+                    origin: line.origin(),
+                    old_lineno: line.old_lineno().map(|n| n as usize),
+                    new_lineno: line.new_lineno().map(|n| n as usize),
+                    content: String::from_utf8_lossy(line.content()).to_string(),
+                });
+            }
+            true
+        };
+
+        diff.foreach(&mut |_,_| {true}, None, None, Some(&mut annotate))?;
+        Ok(lines)
+    }
+
+    /// Zero-based old-side line range of every hunk, per file in diff order. The hunk
+    /// boundaries follow the context and interhunk line counts of `options`.
+    pub fn hunk_ranges(&self, commit_id: Oid, options: &ExtractOptions) -> Result<Vec<(PathBuf, Range<usize>)>, CodeRepositoryError> {
+        let diff = self.get_diff_to_parent(commit_id, options)?;
+        let mut ranges = vec![];
+        for delta_index in 0..diff.deltas().len() {
+            let patch = match Patch::from_diff(&diff, delta_index)? {
+                Some(patch) => patch,
+                None => continue,
+            };
+            let path = match patch.delta().old_file().path() {
+                Some(path) => path.to_path_buf(),
+                None => continue,
+            };
+            for hunk_index in 0..patch.num_hunks() {
+                let (hunk, _) = patch.hunk(hunk_index)?;
+                let start = (hunk.old_start() as usize).saturating_sub(1);
+                ranges.push((path.clone(), start..start + hunk.old_lines() as usize));
+            }
+        }
+        Ok(ranges)
+    }
+
+    /// The lines of the `hunk_index`-th hunk of `file`, each prefixed with its diff origin.
+    pub fn hunk_text(&self, commit_id: Oid, file: &Path, hunk_index: usize) -> Result<Option<String>, CodeRepositoryError> {
+        let diff = self.diff_for_commit(commit_id)?;
+        let delta_index = diff.deltas().position(|delta| {
+            delta.new_file().path() == Some(file) || delta.old_file().path() == Some(file)
+        });
+        let patch = match delta_index {
+            Some(index) => Patch::from_diff(&diff, index)?,
+            None => None,
+        };
+
+        match patch {
+            Some(patch) if hunk_index < patch.num_hunks() => {
+                let mut text = String::new();
+                for line_index in 0..patch.num_lines_in_hunk(hunk_index)? {
+                    let line = patch.line_in_hunk(hunk_index, line_index)?;
+                    text.push(line.origin());
+                    text.push_str(&String::from_utf8_lossy(line.content()));
+                }
+                Ok(Some(text))
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// Content of `path` in the first parent of the commit, i.e. the file the commit's deleted
+    /// lines were removed from. `NotFound` if the commit added the file or has no parent.
+    pub fn old_blob(&self, commit_id: Oid, path: &Path) -> Result<String, CodeRepositoryError> {
+        let commit = self.repo.find_commit(commit_id)?;
+        let not_found = || CodeRepositoryError::NotFound(path.to_path_buf());
+        if commit.parent_count() == 0 {
+            return Err(not_found());
+        }
+        self.blob_at_path(&commit.parent(0)?.tree()?, path)?.ok_or_else(not_found)
+    }
+
+    /// Content of the file at `path` as of the commit itself, the counterpart of `old_blob`.
+    pub fn file_at(&self, oid: Oid, path: &Path) -> Result<String, CodeRepositoryError> {
+        let tree = self.repo.find_commit(oid)?.tree()?;
+        self.blob_at_path(&tree, path)?.ok_or_else(|| CodeRepositoryError::NotInCommit(path.to_path_buf()))
+    }
+
+    fn blob_at_path(&self, tree: &git2::Tree, path: &Path) -> Result<Option<String>, CodeRepositoryError> {
+        let entry = match tree.get_path(path) {
+            Err(error) if error.code() == git2::ErrorCode::NotFound => return Ok(None),
+            entry => entry?,
+        };
+        Ok(Some(self.blob_content(entry.id())?.to_string()))
+    }
+
+    /// Functions of the commit's new file versions that contain an added line or enclose the
+    /// position of a deleted one, per file in path order. Files without a linked grammar,
+    /// deleted, generated and typechanged files are skipped.
+    pub fn changed_functions(&self, commit_id: Oid) -> Result<Vec<(PathBuf, Vec<String>)>, CodeRepositoryError> {
+        let diff = self.diff_for_commit(commit_id)?;
+        let generated = self.generated_paths(&diff)?;
+        let mut changed = vec![];
+
+        for (delta_index, delta) in diff.deltas().enumerate() {
+            let new_file = delta.new_file();
+            let path = match new_file.path() {
+                Some(path) if !new_file.id().is_zero() && !generated.contains(path) && delta.status() != Delta::Typechange => path,
+                _ => continue,
+            };
+            let patch = match Patch::from_diff(&diff, delta_index)? {
+                Some(patch) => patch,
+                None => continue,
+            };
+
+            let mut lines = vec![];
+            for hunk_index in 0..patch.num_hunks() {
+                // 1-based number of the last new line seen, so a deletion maps to the line after it.
+                let mut previous_new_line = (patch.hunk(hunk_index)?.0.new_start() as usize).saturating_sub(1);
+                for line_index in 0..patch.num_lines_in_hunk(hunk_index)? {
+                    let line = patch.line_in_hunk(hunk_index, line_index)?;
+                    match (line.origin(), line.new_lineno()) {
+                        ('+', Some(lineno)) => {
+                            lines.push(lineno as usize - 1);
+                            previous_new_line = lineno as usize;
+                        },
+                        ('-', _) => lines.push(previous_new_line),
+                        (_, Some(lineno)) => previous_new_line = lineno as usize,
+                        _ => {},
+                    }
+                }
+            }
+
+            let content = self.blob_content(new_file.id())?;
+            if let Some(region) = CodeRegion::from_path(path, &content) {
+                let functions = region.enclosing_functions(&lines);
+                if !functions.is_empty() {
+                    changed.push((path.to_path_buf(), functions));
+                }
+            }
+        }
+        changed.sort();
+        Ok(changed)
+    }
+
+    /// Functions of modified C files whose name changed while their body stayed similar.
+    #[cfg(feature = "c-grammar")]
+    pub fn renamed_functions(&self, commit_id: Oid) -> Result<Vec<(String, String)>, CodeRepositoryError> {
+        let diff = self.diff_for_commit(commit_id)?;
+        let mut renames = vec![];
+
+        for delta in diff.deltas() {
+            let (old_file, new_file) = (delta.old_file(), delta.new_file());
+            if old_file.id().is_zero() || new_file.id().is_zero()
+                || new_file.path().and_then(Language::from_path) != Some(Language::C) {
+                continue;
+            }
+
+            let old_functions = CodeRegion::new(&self.blob_content(old_file.id())?).function_definitions();
+            let new_functions = CodeRegion::new(&self.blob_content(new_file.id())?).function_definitions();
+
+            let old_names: HashSet<&String> = old_functions.iter().map(|(name, _)| name).collect();
+            let new_names: HashSet<&String> = new_functions.iter().map(|(name, _)| name).collect();
+            let mut candidates: Vec<&(String, String)> = new_functions.iter()
+                .filter(|(name, _)| !old_names.contains(name))
+                .collect();
+
+            for (old_name, old_body) in old_functions.iter().filter(|(name, _)| !new_names.contains(name)) {
+                let best = candidates.iter().enumerate()
+                    .map(|(index, (_, new_body))| (index, body_similarity(old_body, new_body)))
+                    .filter(|(_, similarity)| *similarity >= RENAME_SIMILARITY)
+                    .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+                if let Some((index, _)) = best {
+                    let (new_name, _) = candidates.remove(index);
+                    renames.push((old_name.clone(), new_name.clone()));
+                }
+            }
+        }
+        Ok(renames)
+    }
+
+    /// Deleted and added lines grouped by the name of the top-level C definition they belong to,
+    /// in order of first appearance. Other lines are grouped under `FILE_LEVEL_DEFINITION`.
+    #[cfg(feature = "c-grammar")]
+    pub fn changes_by_definition(&self, commit_id: Oid) -> Result<Vec<(String, Vec<String>)>, CodeRepositoryError> {
+        let diff = self.diff_for_commit(commit_id)?;
+        let mut regions: HashMap<Oid, CodeRegion> = HashMap::new();
+        for delta in diff.deltas() {
+            for file in [delta.old_file(), delta.new_file()] {
+                if !file.id().is_zero() && file.path().and_then(Language::from_path) == Some(Language::C) {
+                    regions.insert(file.id(), CodeRegion::new(&self.blob_content(file.id())?));
+                }
+            }
+        }
+
+        let mut groups: Vec<(String, Vec<String>)> = vec![];
+        let mut group_lines = |delta: DiffDelta, _maybe_hunk: Option<DiffHunk>, line: DiffLine| -> bool {
+            let (blob_id, lineno) = match line.origin_value() {
+                git2::DiffLineType::Deletion => (delta.old_file().id(), line.old_lineno()),
+                git2::DiffLineType::Addition => (delta.new_file().id(), line.new_lineno()),
+                _ => return true,
+            };
+            let definition = match (regions.get(&blob_id), lineno) {
+                (Some(region), Some(lineno)) => region.top_level_name_at(lineno as usize - 1),
+                _ => None,
+            }.unwrap_or_else(|| FILE_LEVEL_DEFINITION.to_string());
+
+            let content = String::from_utf8_lossy(line.content()).trim_end_matches('\n').to_string();
+            match groups.iter_mut().find(|(name, _)| *name == definition) {
+                Some((_, lines)) => lines.push(content),
+                None => groups.push((definition, vec![content])),
+            }
+            true
+        };
+
+        diff.foreach(&mut |_,_| {true}, None, None, Some(&mut group_lines))?;
+        Ok(groups)
+    }
+
+    /// The commit's diff to its parent as raw patch bytes, suitable for `git apply`.
+    pub fn as_patch(&self, commit_id: Oid) -> Result<Vec<u8>, CodeRepositoryError> {
+        let diff = self.diff_for_commit(commit_id)?;
+        let mut patch = vec![];
+        diff.print(git2::DiffFormat::Patch, |_delta, _maybe_hunk, line| {
+            if let '+' | '-' | ' ' = line.origin() {
+                patch.push(line.origin() as u8);
+            }
+            patch.extend_from_slice(line.content());
+            true
+        })?;
+        Ok(patch)
+    }
+
+    /// `as_patch` as text, with content that isn't valid UTF-8 converted lossily.
+    pub fn unified_diff(&self, oid: Oid) -> Result<String, CodeRepositoryError> {
+        Ok(String::from_utf8_lossy(&self.as_patch(oid)?).to_string())
+    }
+
+    /// Stable hash of the commit's diff that, like `git patch-id`, is shared by cherry-picks
+    /// and rebased copies of the same change.
+    pub fn patch_id(&self, commit_id: Oid) -> Result<String, CodeRepositoryError> {
+        let diff = self.diff_for_commit(commit_id)?;
+        Ok(diff.patchid(None)?.to_string())
+    }
+
+    /// Parameters of C function signatures in `file` that changed, aligned by position.
+    #[cfg(feature = "c-grammar")]
+    pub fn signature_param_changes(&self, commit_id: Oid, file: &Path) -> Result<Vec<ParamChange>, CodeRepositoryError> {
+        let diff = self.diff_for_commit(commit_id)?;
+        let delta = match diff.deltas().find(|d| d.new_file().path() == Some(file)) {
+            Some(delta) if !delta.old_file().id().is_zero() && !delta.new_file().id().is_zero() => delta,
+            _ => return Ok(vec![]),
+        };
+
+        let old_signatures = CodeRegion::new(&self.blob_content(delta.old_file().id())?).function_signatures();
+        let new_signatures = CodeRegion::new(&self.blob_content(delta.new_file().id())?).function_signatures();
+        let mut changes = vec![];
+        for (function, old_params) in &old_signatures {
+            let new_params = match new_signatures.iter().find(|(name, _)| name == function) {
+                Some((_, params)) => params,
+                None => continue,
+            };
+            for position in 0..old_params.len().max(new_params.len()) {
+                let old_param = old_params.get(position).cloned().unwrap_or_default();
+                let new_param = new_params.get(position).cloned().unwrap_or_default();
+                if old_param != new_param {
+                    changes.push(ParamChange{function: function.clone(), old_param, new_param});
+                }
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Count added and deleted lines per language, keyed by `Language::name` or `"other"`.
+    pub fn churn_by_language(&self, commit_id: Oid) -> Result<HashMap<String, (usize, usize)>, CodeRepositoryError> {
+        let diff = self.diff_for_commit(commit_id)?;
+        let mut churn: HashMap<String, (usize, usize)> = HashMap::new();
+
+        let mut count_lines = |delta: DiffDelta, _maybe_hunk: Option<DiffHunk>, line: DiffLine| -> bool {
+            let language = delta.new_file().path()
+                .or_else(|| delta.old_file().path())
+                .and_then(Language::from_path)
+                .map_or("other", |l| l.name());
+            match line.origin_value() {
+                git2::DiffLineType::Addition => churn.entry(language.into()).or_insert((0, 0)).0 += 1,
+                git2::DiffLineType::Deletion => churn.entry(language.into()).or_insert((0, 0)).1 += 1,
+                _ => {},
+            }
+            true
+        };
+
+        diff.foreach(&mut |_,_| {true}, None, None, Some(&mut count_lines))?;
+        Ok(churn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+    use tempdir::TempDir;
+    use anyhow::Result;
+    use std::path::Path;
+    use git2::Repository;
+    use std::fs::File;
+    use std::io::prelude::*;
+    use indoc::indoc;
+
+    fn with_empty_repo(test: fn(&Path) -> ()) -> Result<()>{
+        let repo_dir = TempDir::new("empty_repository")?;
+        let _repo = Repository::init(repo_dir.path())?;
+
+        test(repo_dir.path());
+
+        Ok(())
+    }
+
+    fn commit_file(repo_dir: &Path, filename: &str, content: &str, msg: &str) -> Result<()>{
+        commit_files(repo_dir, &[(filename, content)], msg)
+    }
+
+    fn commit_files(repo_dir: &Path, files: &[(&str, &str)], msg: &str) -> Result<()>{
+        for (filename, content) in files {
+            let mut new_file = File::create(repo_dir.join(filename))?;
+            new_file.write_all(content.as_bytes())?;
+
+            process::Command::new("git")  
+                .args(["add", filename])
+                .current_dir(repo_dir)
+                .output()?;
+        }
+        process::Command::new("git")  
+            .args(["commit", "-a", "-m", msg])
+            .current_dir(repo_dir)
+            .output()?;
+        Ok(())
+    }
+
+    fn commit_file_at(repo_dir: &Path, filename: &str, content: &str, msg: &str, time: i64) -> Result<()> {
+        std::fs::write(repo_dir.join(filename), content)?;
+        let date = format!("@{} +0000", time);
+        process::Command::new("git")
+            .args(["add", filename])
+            .current_dir(repo_dir)
+            .output()?;
+        process::Command::new("git")
+            .args(["commit", "-q", "-m", msg])
+            .env("GIT_AUTHOR_DATE", &date)
+            .env("GIT_COMMITTER_DATE", &date)
+            .current_dir(repo_dir)
+            .output()?;
+        Ok(())
+    }
+
+    fn create_temporary_repository() -> Result<TempDir> {
+        let repo_dir = TempDir::new("buggy_repository")?;
+        Repository::init(repo_dir.path())?;
+        Ok(repo_dir)
+    }
+
+    fn with_repo_containing_bugs(test: fn(&Path) -> ()) -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        commit_file(repo_dir.path(), "foo", "", "Create foo")?;
+
+        test(repo_dir.path());
+
+        Ok(())
+    }
+
+    fn with_repo_containing_function_pointer_bug(test: fn(&Path) -> ()) -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let buggy_code = indoc! {r#"
+            #include <stdio.h>
+
+            void foo(int i) {
+              printf("%i\n", i);
+            }
+
+            typedef void (*fpt)(unsigned int i);
+
+            int main() {
+              fpt fp;
+
+              fp = foo;
+
+              foo(10);
+              fp(20);
+            }
+        "#};
+
+        let fixed_bug = indoc!{r#"
+            #include <stdio.h>
+
+            void foo(int i) {
+              printf("%i\n", i);
+            }
+
+            typedef void (*fpt)(int i);
+
+            int main() {
+              fpt fp;
+
+              fp = foo;
+
+              foo(10);
+              fp(20);
+            }
+        "#};
+
+        commit_file(repo_dir.path(), "main.c", buggy_code, "this should work!")?;
+        commit_file(repo_dir.path(), "main.c", fixed_bug, "fixed bug")?;
+
+        test(repo_dir.path());
+
+        Ok(())
+
+    }
+
+    fn with_repo_containing_linear_history(test: fn(&Path) -> ()) -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        commit_file(repo_dir.path(), "main.c", "int a;\n", "fix first")?;
+        commit_file(repo_dir.path(), "main.c", "int b;\n", "fix second")?;
+        commit_file(repo_dir.path(), "main.c", "int c;\n", "fix third")?;
+
+        test(repo_dir.path());
+
+        Ok(())
+    }
+
+    fn with_repo_containing_merge(test: fn(&Path) -> ()) -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_files(path, &[("main.c", "int a;\n"), ("other.c", "int x;\n")], "initial commit")?;
+        git(path, &["checkout", "-q", "-b", "feature"]);
+        commit_file(path, "other.c", "int y;\n", "fix other on feature")?;
+        git(path, &["checkout", "-q", "master"]);
+        commit_file(path, "main.c", "int b;\n", "fix main on master")?;
+        git(path, &["merge", "-q", "--no-ff", "-m", "merge feature", "feature"]);
+
+        test(path);
+
+        Ok(())
+    }
+
+    fn with_repo_containing_mixed_languages(test: fn(&Path) -> ()) -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        commit_files(repo_dir.path(), &[
+            ("main.c", "int main() {\n  return 1;\n}\n"),
+            ("script.py", "print('hello')\n"),
+        ], "initial version")?;
+        commit_files(repo_dir.path(), &[
+            ("main.c", "int main() {\n  int ok = 0;\n  return ok;\n}\n"),
+            ("script.py", "print('hello, world')\nprint('bye')\n"),
+        ], "fix exit code")?;
+
+        test(repo_dir.path());
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_repository() -> Result<()> {
+        with_empty_repo(|repo_path: &Path| {
+            let repository = CodeRepository::new(repo_path.to_str().unwrap());
+            assert!(repository.is_ok());
+        })
+    }
+
+    #[test]
+    fn wrapped_repository_is_shared() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        commit_file(repo_dir.path(), "main.c", "int a;\n", "fix first")?;
+        let some_repo = CodeRepository::from_repo(Repository::open(repo_dir.path())?);
+        let head = some_repo.repo().head()?.target().unwrap();
+        assert_eq!(some_repo.commits_matching(&[Regex::new("fix").unwrap()])?, vec![head]);
+        Ok(())
+    }
+
+    #[test]
+    fn repository_is_discovered_from_subdirectory() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        commit_file(repo_dir.path(), "main.c", "int a;\n", "fix first")?;
+        let nested = repo_dir.path().join("src").join("nested");
+        std::fs::create_dir_all(&nested)?;
+
+        assert!(CodeRepository::new(&nested).is_err());
+        let some_repo = CodeRepository::discover(&nested)?;
+        assert_eq!(some_repo.commits_matching(&[Regex::new("fix").unwrap()])?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn bare_repository_is_searched() -> Result<()> {
+        let repo_dir = TempDir::new("bare_repository")?;
+        let repo = Repository::init_bare(repo_dir.path())?;
+        let blob = repo.blob(b"int a;\n")?;
+        let mut tree = repo.treebuilder(None)?;
+        tree.insert("main.c", blob, 0o100644)?;
+        let tree = repo.find_tree(tree.write()?)?;
+        let signature = git2::Signature::now("Test", "test@example.com")?;
+        let commit = repo.commit(Some("HEAD"), &signature, &signature, "fix bare", &tree, &[])?;
+
+        let patterns = vec![Regex::new("fix").unwrap()];
+        assert_eq!(CodeRepository::open_bare(repo_dir.path())?.commits_matching(&patterns)?, vec![commit]);
+        assert_eq!(CodeRepository::new(repo_dir.path())?.commits_matching(&patterns)?, vec![commit]);
+        assert!(CodeRepository::open_bare(".").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn find_no_commits_on_empty_repository() -> Result<()> {
+        with_empty_repo(|repo_path: &Path| {
+            let empty_repo = CodeRepository::new(repo_path.to_str().unwrap());
+            let patterns: Vec<Regex> = vec![];
+            assert_eq!(empty_repo.unwrap().commits_matching(&patterns).unwrap().len(), 0);
+        })
+    }
+
+    fn number_of_commits_in_this_repo() -> usize {
+        let stdout = process::Command::new("git")
+            .args(["rev-list", "--all", "--count"])
+            .output()
+            .expect("working git command").stdout;
+        String::from_utf8_lossy(&stdout).trim().parse().unwrap()
+    }
+
+    #[test]
+    fn find_all_commits_on_this_repo_with_matchall_pattern() {
+        let some_repo = CodeRepository::new(".").unwrap();
+        let patterns = vec![Regex::new(".*").unwrap()];
+        assert_eq!(some_repo.commits_matching(&patterns).unwrap().len(), number_of_commits_in_this_repo());
+    }
+
+    #[test]
+    fn matching_commits_are_counted() {
+        let some_repo = CodeRepository::new(".").unwrap();
+        let patterns = vec![Regex::new("Initial").unwrap()];
+        assert_eq!(some_repo.count_matching(&patterns).unwrap(), some_repo.commits_matching(&patterns).unwrap().len());
+        assert_eq!(some_repo.count_matching(&[]).unwrap(), 0);
+    }
+
+    #[test]
+    fn commits_are_filtered_with_patterns() {
+        let some_repo = CodeRepository::new(".").unwrap();
+        let patterns = vec![Regex::new("Initial").unwrap()];
+        assert!(some_repo.commits_matching(&patterns).unwrap().len() < number_of_commits_in_this_repo());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_matching_agrees_with_sequential_matching() {
+        let some_repo = CodeRepository::new(".").unwrap();
+        let patterns = vec![Regex::new("Initial").unwrap()];
+        assert_eq!(some_repo.commits_matching_par(&patterns).unwrap(), some_repo.commits_matching(&patterns).unwrap());
+    }
+
+    fn initial_commit(repo_path: &Path) -> String {
+        let stdout = process::Command::new("git")
+            .args(["rev-list", "--max-parents=0", "HEAD"])
+            .current_dir(repo_path)
+            .output()
+            .expect("querying first commit id")
+            .stdout;
+        String::from_utf8_lossy(&stdout).trim().to_string()
+    }
+
+    #[test]
+    fn extract_empty_string_from_initial_commit_adding_empty_file() -> Result<()> {
+        with_repo_containing_bugs(|path: &Path| {
+            let some_repo = CodeRepository::new(path.to_str().unwrap()).unwrap();
+            let commit_id = initial_commit(path);
+            dbg!(&commit_id);
+            let commit = git2::Oid::from_str(&commit_id).unwrap();
+            let changes: String = some_repo.get_changes(commit).unwrap();
+            assert!(changes.is_empty());
+        })
+    }
+    
+    #[test]
+    fn extract_empty_string_from_initial_commit_adding_nonempty_file() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_file(path, "main.c", "int a;\n", "add main")?;
+        let some_repo = CodeRepository::new(path.to_str().unwrap())?;
+        let commit = git2::Oid::from_str(&initial_commit(path))?;
+        assert!(some_repo.get_changes(commit)?.is_empty());
+        Ok(())
+    }
+
+    /// Delete the loose object of `revision`, leaving the repository corrupt.
+    fn remove_object(repo_path: &Path, revision: &str) -> Result<()> {
+        let oid = git(repo_path, &["rev-parse", revision]);
+        std::fs::remove_file(repo_path.join(".git/objects").join(&oid[..2]).join(&oid[2..]))?;
+        Ok(())
+    }
+
+    fn get_last_commit(repo_path: &str) -> String {
+        let stdout = process::Command::new("git")
+            .args(["rev-list", "--max-count=1", "master"])
+            .current_dir(repo_path)
+            .output()
+            .expect("querying the last commit on master")
+            .stdout;
+        String::from_utf8_lossy(&stdout).trim().to_string()
+    }
+
+    fn git(repo_path: &Path, args: &[&str]) -> String {
+        let stdout = process::Command::new("git")
+            .args(args)
+            .current_dir(repo_path)
+            .output()
+            .expect("working git command")
+            .stdout;
+        String::from_utf8_lossy(&stdout).trim().to_string()
+    }
+
+    #[test]
+    fn extract_string_from_commit() -> Result<()>{
+        with_repo_containing_function_pointer_bug(|project_path| {
+            let project_path_str = project_path.to_str().unwrap();
+            let some_repo = CodeRepository::new(project_path_str).unwrap();
+            let commit = git2::Oid::from_str(&get_last_commit(project_path_str)).unwrap();
+            let changes: String = some_repo.get_changes(commit).unwrap();
+            dbg!(&changes);
+            assert!(changes.contains("typedef"));
+            assert!(!changes.contains("fp(20)"));
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn extract_line_from_commit() -> Result<()> {
+        with_repo_containing_function_pointer_bug(|project_path| {
+            let prj_str = project_path.to_str().unwrap();
+            let some_repo = CodeRepository::new(prj_str).unwrap();
+            let commit = git2::Oid::from_str(&get_last_commit(prj_str)).unwrap();
+            let changes: String = some_repo.get_changes(commit).unwrap();
+            dbg!(&changes);
+            assert!(changes.contains("typedef"));
+            assert!(!changes.contains("main"));
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn extract_line_from_commit_to_changeset() -> Result<()> {
+        with_repo_containing_function_pointer_bug(|project_path| {
+            let prj_str = project_path.to_str().unwrap();
+            let some_repo = CodeRepository::new(prj_str).unwrap();
+            let commit = git2::Oid::from_str(&get_last_commit(prj_str)).unwrap();
+            let changes = some_repo.get_change_sets(commit).unwrap();
+            let first_change = changes.first().unwrap();
+            let expected_line: usize = 6;
+            dbg!(first_change.ranges());
+            assert!(first_change.ranges().iter().any(|r| r.contains(&expected_line)));
+            assert!(first_change.text_ranges().concat().contains("typedef"));
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn file_content_is_read_at_commit() -> Result<()> {
+        with_repo_containing_linear_history(|repo_path| {
+            let some_repo = CodeRepository::new(repo_path).unwrap();
+            let head = some_repo.repo.head().unwrap().target().unwrap();
+            assert_eq!(some_repo.file_at(head, Path::new("main.c")).unwrap(), "int c;\n");
+            assert_eq!(some_repo.old_blob(head, Path::new("main.c")).unwrap(), "int b;\n");
+            assert!(matches!(some_repo.file_at(head, Path::new("missing.c")), Err(CodeRepositoryError::NotInCommit(_))));
+        })
+    }
+
+    #[test]
+    fn removed_file_is_a_single_change_set() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_files(path, &[("keep.c", "int k;\n"), ("gone.c", "int a;\nint b;\nint c;\n")], "initial commit")?;
+        git(path, &["rm", "-q", "gone.c"]);
+        git(path, &["commit", "-q", "-m", "remove gone.c"]);
+
+        let some_repo = CodeRepository::new(path)?;
+        let head = some_repo.repo.head()?.target().unwrap();
+        let change_sets = some_repo.get_change_sets(head)?;
+        assert_eq!(change_sets.len(), 1);
+        assert_eq!(change_sets[0].filename, Path::new("gone.c"));
+        assert_eq!(change_sets[0].ranges(), vec![0..3]);
+        assert_eq!(change_sets[0].text_ranges(), vec!["int a;\nint b;\nint c;"]);
+        assert_eq!(change_sets[0].status, ChangeStatus::Deleted);
+        Ok(())
+    }
+
+    #[test]
+    fn churn_is_counted_per_language() -> Result<()> {
+        with_repo_containing_mixed_languages(|project_path| {
+            let prj_str = project_path.to_str().unwrap();
+            let some_repo = CodeRepository::new(prj_str).unwrap();
+            let commit = git2::Oid::from_str(&get_last_commit(prj_str)).unwrap();
+            let churn = some_repo.churn_by_language(commit).unwrap();
+            assert_eq!(churn.get("c"), Some(&(2, 1)));
+            assert_eq!(churn.get("python"), Some(&(2, 1)));
+            assert!(!churn.contains_key("other"));
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn extract_changes_only_for_requested_language() -> Result<()> {
+        with_repo_containing_mixed_languages(|project_path| {
+            let prj_str = project_path.to_str().unwrap();
+            let some_repo = CodeRepository::new(prj_str).unwrap();
+            let commit = git2::Oid::from_str(&get_last_commit(prj_str)).unwrap();
+            let changes = some_repo.get_changes_for_language(commit, Language::C).unwrap();
+            assert_eq!(changes, "  return 1;\n");
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn extraction_stops_when_callback_declines() -> Result<()> {
+        with_repo_containing_mixed_languages(|project_path| {
+            let prj_str = project_path.to_str().unwrap();
+            let some_repo = CodeRepository::new(prj_str).unwrap();
+            let commit = git2::Oid::from_str(&get_last_commit(prj_str)).unwrap();
+            let mut seen = 0;
+            let partial = some_repo.get_changes_while(commit, |_| { seen += 1; seen == 1 }).unwrap();
+            assert!(partial.truncated);
+            assert_eq!(partial.changes.lines().count(), 1);
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn extraction_is_complete_when_callback_never_declines() -> Result<()> {
+        with_repo_containing_mixed_languages(|project_path| {
+            let prj_str = project_path.to_str().unwrap();
+            let some_repo = CodeRepository::new(prj_str).unwrap();
+            let commit = git2::Oid::from_str(&get_last_commit(prj_str)).unwrap();
+            let partial = some_repo.get_changes_while(commit, |_| true).unwrap();
+            assert!(!partial.truncated);
+            assert_eq!(partial.changes, some_repo.get_changes(commit).unwrap());
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn partial_extraction_skips_the_files_get_changes_skips() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_files(path, &[("main.c", "int a;\n"), ("data.bin", "a\0b\n"), ("Cargo.lock", "old\n")], "add files")?;
+        commit_files(path, &[("main.c", "int b;\n"), ("data.bin", "c\0d\n"), ("Cargo.lock", "new\n")], "change files")?;
+
+        let some_repo = CodeRepository::new(path.to_str().unwrap())?;
+        let head = git2::Oid::from_str(&git(path, &["rev-parse", "HEAD"]))?;
+        let partial = some_repo.get_changes_while(head, |_| true)?;
+        assert_eq!(partial.changes, "int a;\n");
+        assert_eq!(partial.changes, some_repo.get_changes(head)?);
+        Ok(())
+    }
+
+    #[test]
+    fn recent_changes_are_limited_to_count() -> Result<()> {
+        with_repo_containing_linear_history(|project_path| {
+            let some_repo = CodeRepository::new(project_path.to_str().unwrap()).unwrap();
+            let patterns = vec![Regex::new("fix").unwrap()];
+            let recent = some_repo.recent_changes(2, &patterns).unwrap();
+            assert_eq!(recent.len(), 2);
+            assert_eq!(recent[0].1, "int b;\n");
+            assert_eq!(recent[1].1, "int a;\n");
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn hunks_are_addressed_by_index() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let original: String = (0..20).map(|i| format!("line {}\n", i)).collect();
+        let changed = original.replace("line 2\n", "line two\n").replace("line 17\n", "line seventeen\n");
+        commit_file(repo_dir.path(), "lines.txt", &original, "add lines")?;
+        commit_file(repo_dir.path(), "lines.txt", &changed, "spell out numbers")?;
+
+        let prj_str = repo_dir.path().to_str().unwrap();
+        let some_repo = CodeRepository::new(prj_str)?;
+        let commit = git2::Oid::from_str(&get_last_commit(prj_str))?;
+        let file = Path::new("lines.txt");
+        let first = some_repo.hunk_text(commit, file, 0)?.unwrap();
+        let second = some_repo.hunk_text(commit, file, 1)?.unwrap();
+        assert!(first.contains("-line 2\n+line two\n"));
+        assert!(second.contains("-line 17\n+line seventeen\n"));
+        assert_ne!(first, second);
+        assert!(some_repo.hunk_text(commit, file, 2)?.is_none());
+        Ok(())
+    }
+
+    #[cfg(feature = "c-grammar")]
+    #[test]
+    fn renamed_function_is_reported() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let body = "{\n  int twice = i * 2;\n  return twice;\n}\n";
+        commit_file(repo_dir.path(), "main.c", &format!("int foo(int i) {}int main() {{ return 0; }}\n", body), "add foo")?;
+        commit_file(repo_dir.path(), "main.c", &format!("int bar(int i) {}int main() {{ return 0; }}\n", body), "rename foo")?;
+
+        let prj_str = repo_dir.path().to_str().unwrap();
+        let some_repo = CodeRepository::new(prj_str)?;
+        let commit = git2::Oid::from_str(&get_last_commit(prj_str))?;
+        assert_eq!(some_repo.renamed_functions(commit)?, vec![("foo".to_string(), "bar".to_string())]);
+        Ok(())
+    }
+
+    #[cfg(feature = "c-grammar")]
+    #[test]
+    fn dissimilar_functions_are_not_renames() {
+        assert!(body_similarity("{ return a + b; }", "{ while (1) { sleep(); } }") < RENAME_SIMILARITY);
+        assert_eq!(body_similarity("{ return a; }", "{\n  return a;\n}"), 1.0);
+    }
+
+    #[cfg(feature = "c-grammar")]
+    #[test]
+    fn consecutive_commits_share_cached_blobs() -> Result<()> {
+        with_repo_containing_linear_history(|project_path| {
+            let some_repo = CodeRepository::new(project_path.to_str().unwrap()).unwrap();
+            let patterns = vec![Regex::new("fix").unwrap()];
+            for oid in some_repo.commits_matching(&patterns).unwrap() {
+                some_repo.renamed_functions(oid).unwrap();
+            }
+            assert!(some_repo.cache_stats().hits > 0);
+        })?;
+        Ok(())
+    }
+
+    #[cfg(feature = "c-grammar")]
+    #[test]
+    fn disabled_blob_cache_never_hits() -> Result<()> {
+        with_repo_containing_linear_history(|project_path| {
+            let some_repo = CodeRepository::new(project_path.to_str().unwrap()).unwrap()
+                .with_blob_cache_capacity(0);
+            let patterns = vec![Regex::new("fix").unwrap()];
+            for oid in some_repo.commits_matching(&patterns).unwrap() {
+                some_repo.renamed_functions(oid).unwrap();
+            }
+            assert_eq!(some_repo.cache_stats().hits, 0);
+            assert!(some_repo.cache_stats().misses > 0);
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn repository_can_be_sent_to_another_thread() {
+        fn assert_send<T: Send>() {}
+        assert_send::<CodeRepository>();
+    }
+
+    #[test]
+    fn patch_of_commit_is_a_git_diff() -> Result<()> {
+        with_repo_containing_function_pointer_bug(|project_path| {
+            let prj_str = project_path.to_str().unwrap();
+            let some_repo = CodeRepository::new(prj_str).unwrap();
+            let commit = git2::Oid::from_str(&get_last_commit(prj_str)).unwrap();
+            let patch = String::from_utf8(some_repo.as_patch(commit).unwrap()).unwrap();
+            assert!(patch.starts_with("diff --git a/main.c b/main.c"));
+            assert!(patch.contains("-typedef void (*fpt)(unsigned int i);\n"));
+            assert!(patch.contains("+typedef void (*fpt)(int i);\n"));
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn unified_diff_has_hunk_headers() -> Result<()> {
+        with_repo_containing_function_pointer_bug(|project_path| {
+            let prj_str = project_path.to_str().unwrap();
+            let some_repo = CodeRepository::new(prj_str).unwrap();
+            let commit = git2::Oid::from_str(&get_last_commit(prj_str)).unwrap();
+            let diff = some_repo.unified_diff(commit).unwrap();
+            assert!(diff.lines().any(|line| line.starts_with("@@ -") && line.contains(" @@")));
+            assert!(diff.contains("\n-typedef void (*fpt)(unsigned int i);\n+typedef void (*fpt)(int i);\n"));
+        })?;
+        Ok(())
+    }
+
+    #[cfg(feature = "c-grammar")]
+    #[test]
+    fn changes_are_grouped_by_definition() -> Result<()> {
+        with_repo_containing_function_pointer_bug(|project_path| {
+            let prj_str = project_path.to_str().unwrap();
+            let some_repo = CodeRepository::new(prj_str).unwrap();
+            let commit = git2::Oid::from_str(&get_last_commit(prj_str)).unwrap();
+            let groups = some_repo.changes_by_definition(commit).unwrap();
+            assert_eq!(groups, vec![("fpt".to_string(), vec![
+                "typedef void (*fpt)(unsigned int i);".to_string(),
+                "typedef void (*fpt)(int i);".to_string(),
+            ])]);
+        })?;
+        Ok(())
+    }
+
+    #[cfg(feature = "c-grammar")]
+    #[test]
+    fn include_changes_are_file_level() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        commit_file(repo_dir.path(), "main.c", "#include <stdio.h>\nint main() { return 0; }\n", "add main")?;
+        commit_file(repo_dir.path(), "main.c", "#include <stdlib.h>\nint main() { return 0; }\n", "swap include")?;
+
+        let prj_str = repo_dir.path().to_str().unwrap();
+        let some_repo = CodeRepository::new(prj_str)?;
+        let commit = git2::Oid::from_str(&get_last_commit(prj_str))?;
+        let groups = some_repo.changes_by_definition(commit)?;
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].0, FILE_LEVEL_DEFINITION);
+        assert_eq!(groups[0].1.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn generated_files_are_skipped() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let minified = "var a=function(b,c){return b+c},d=a(1,2);".repeat(50);
+        commit_files(repo_dir.path(), &[
+            ("main.c", "int a;\n"),
+            ("bundle.js", &minified),
+        ], "add sources")?;
+        commit_files(repo_dir.path(), &[
+            ("main.c", "int b;\n"),
+            ("bundle.js", &minified.replace("d=a", "e=a")),
+        ], "fix sources")?;
+
+        let prj_str = repo_dir.path().to_str().unwrap();
+        let some_repo = CodeRepository::new(prj_str)?;
+        let commit = git2::Oid::from_str(&get_last_commit(prj_str))?;
+        assert_eq!(some_repo.get_changes(commit)?, "int a;\n");
+        let change_sets = some_repo.get_change_sets(commit)?;
+        assert_eq!(change_sets.len(), 1);
+        assert_eq!(change_sets[0].filename, PathBuf::from("main.c"));
+        Ok(())
+    }
+
+    #[test]
+    fn classifying_files_does_not_fill_the_blob_cache() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_files(path, &[("main.c", "int a;\n"), ("data.bin", "a\0b\n")], "add files")?;
+        commit_files(path, &[("main.c", "int b;\n"), ("data.bin", "c\0d\n")], "change files")?;
+
+        let some_repo = CodeRepository::new(path)?;
+        let head = git2::Oid::from_str(&git(path, &["rev-parse", "HEAD"]))?;
+        assert_eq!(some_repo.get_changes(head)?, "int a;\n");
+        assert_eq!(some_repo.cache_stats(), CacheStats::default());
+        Ok(())
+    }
+
+    #[test]
+    fn extracted_commit_carries_its_message() -> Result<()> {
+        with_repo_containing_function_pointer_bug(|project_path| {
+            let some_repo = CodeRepository::new(project_path.to_str().unwrap()).unwrap();
+            let patterns = vec![Regex::new("bug").unwrap()];
+            let extracted = some_repo.matching_changes_full(&patterns).unwrap();
+            assert_eq!(extracted.len(), 1);
+            assert_eq!(extracted[0].message, "fixed bug\n");
+            assert!(extracted[0].changes.contains("typedef"));
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn scan_commits_of_linked_worktree() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        commit_file(repo_dir.path(), "main.c", "int a;\n", "initial commit")?;
+        let worktrees_dir = TempDir::new("worktrees")?;
+        let worktree_path = worktrees_dir.path().join("feature");
+        process::Command::new("git")
+            .args(["worktree", "add", "-b", "feature", worktree_path.to_str().unwrap()])
+            .current_dir(repo_dir.path())
+            .output()?;
+        commit_file(&worktree_path, "main.c", "int b;\n", "fix in worktree")?;
+
+        let patterns = vec![Regex::new("fix").unwrap()];
+        let main_repo = CodeRepository::new(repo_dir.path())?;
+        assert!(main_repo.commits_matching(&patterns)?.is_empty());
+        let worktree_repo = CodeRepository::open_worktree(repo_dir.path(), "feature")?;
+        assert_eq!(worktree_repo.commits_matching(&patterns)?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn cherry_pick_has_same_patch_id() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_files(path, &[("main.c", "int a;\n"), ("other.c", "int x;\n")], "initial commit")?;
+        git(path, &["branch", "other"]);
+        commit_file(path, "main.c", "int b;\n", "fix main")?;
+        let original = git(path, &["rev-parse", "HEAD"]);
+        git(path, &["checkout", "-q", "other"]);
+        commit_file(path, "other.c", "int y;\n", "change other")?;
+        git(path, &["cherry-pick", original.as_str()]);
+        let picked = git(path, &["rev-parse", "HEAD"]);
+        assert_ne!(original, picked);
+
+        let some_repo = CodeRepository::new(path.to_str().unwrap())?;
+        let original_id = some_repo.patch_id(git2::Oid::from_str(&original)?)?;
+        assert_eq!(original_id, some_repo.patch_id(git2::Oid::from_str(&picked)?)?);
+        let unrelated = git(path, &["rev-parse", "HEAD~1"]);
+        assert_ne!(original_id, some_repo.patch_id(git2::Oid::from_str(&unrelated)?)?);
+        Ok(())
+    }
+
+    #[test]
+    fn missing_newline_marker_is_skipped_by_default() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        commit_file(repo_dir.path(), "main.c", "int a;\nint b;", "add main")?;
+        commit_file(repo_dir.path(), "main.c", "int a;\nint c;", "fix main")?;
+
+        let prj_str = repo_dir.path().to_str().unwrap();
+        let some_repo = CodeRepository::new(prj_str)?;
+        let commit = git2::Oid::from_str(&get_last_commit(prj_str))?;
+        assert_eq!(some_repo.get_changes(commit)?, "int b;");
+
+        let preserved = some_repo.get_changes_with(commit, ExtractOptions::new().eof_newline(EofNewline::Preserve))?;
+        assert!(preserved.starts_with("int b;"));
+        assert!(preserved.contains("No newline at end of file"));
+        Ok(())
+    }
+
+    #[test]
+    fn file_turned_into_symlink_is_a_skipped_typechange() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_files(path, &[("main.c", "int a;\n"), ("other.c", "int b;\n")], "add sources")?;
+        std::fs::remove_file(path.join("main.c"))?;
+        std::os::unix::fs::symlink("other.c", path.join("main.c"))?;
+        git(path, &["commit", "-q", "-a", "-m", "link main"]);
+
+        let some_repo = CodeRepository::new(path.to_str().unwrap())?;
+        let commit = git2::Oid::from_str(&git(path, &["rev-parse", "HEAD"]))?;
+        assert_eq!(some_repo.file_statuses(commit)?, vec![(PathBuf::from("main.c"), Delta::Typechange)]);
+        assert!(some_repo.get_changes(commit)?.is_empty());
+        assert!(some_repo.get_change_sets(commit)?.is_empty());
+
+        let included = some_repo.get_changes_with(commit, ExtractOptions::new().include_typechange(true))?;
+        assert_eq!(included, "int a;\n");
+        Ok(())
+    }
+
+    #[test]
+    fn change_sets_of_unknown_commit_are_an_error() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_file(path, "main.c", "int a;\n", "add main")?;
+        let some_repo = CodeRepository::new(path.to_str().unwrap())?;
+        assert!(some_repo.get_change_sets(git2::Oid::zero()).is_err());
+        let root = git2::Oid::from_str(&git(path, &["rev-parse", "HEAD"]))?;
+        let change_sets = some_repo.get_change_sets(root)?;
+        assert_eq!(change_sets.len(), 1);
+        assert_eq!(change_sets[0].status, ChangeStatus::Added);
+        Ok(())
+    }
+
+    #[test]
+    fn dump_changes_writes_one_file_per_commit() -> Result<()> {
+        with_repo_containing_function_pointer_bug(|project_path| {
+            let some_repo = CodeRepository::new(project_path.to_str().unwrap()).unwrap();
+            let out_dir = TempDir::new("dumped_changes").unwrap();
+            let patterns = vec![Regex::new("bug").unwrap()];
+            let short_id = git(project_path, &["rev-parse", "--short", "HEAD"]);
+            let dumped_file = out_dir.path().join(format!("{}.txt", short_id));
+            std::fs::write(&dumped_file, "stale").unwrap();
+
+            assert_eq!(some_repo.dump_changes(&patterns, out_dir.path()).unwrap(), 1);
+            let dumped = std::fs::read_to_string(&dumped_file).unwrap();
+            assert_eq!(dumped, "typedef void (*fpt)(unsigned int i);\n");
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn extraction_stops_when_budget_is_exceeded() -> Result<()> {
+        with_repo_containing_linear_history(|project_path| {
+            let some_repo = CodeRepository::new(project_path.to_str().unwrap()).unwrap();
+            let patterns = vec![Regex::new("fix").unwrap()];
+            let full = some_repo.matching_changes_bounded(&patterns, usize::MAX).unwrap();
+            assert!(!full.budget_exceeded);
+            assert_eq!(full.changes.len(), 3);
+
+            let bounded = some_repo.matching_changes_bounded(&patterns, 10).unwrap();
+            assert!(bounded.budget_exceeded);
+            assert_eq!(bounded.changes.len(), 1);
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn commits_are_classified() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_files(path, &[("main.c", "int main() {\n  return 0;\n}\n"), ("README.md", "# main\n")], "add main")?;
+        commit_file(path, "main.c", "int main() {\n  return 1;\n}\n", "fix: return failure")?;
+        commit_file(path, "main.c", "int main() {\n  return 2;\n}\n", "docs(main): explain exit code")?;
+        commit_file(path, "main.c", "int main() {\n  return 3;\n}\n", "feat!: new exit code")?;
+        commit_file(path, "README.md", "# main program\n", "describe program")?;
+        commit_file(path, "main.c", "int main() {\n    return 3;\n}\n", "reindent")?;
+        commit_file(path, "main.c", "int main() {\n    return 4;\n}\n", "change exit code")?;
+
+        let some_repo = CodeRepository::new(path.to_str().unwrap())?;
+        let category_of = |rev: &str| some_repo.classify_commit(git2::Oid::from_str(&git(path, &["rev-parse", rev])).unwrap()).unwrap();
+        assert_eq!(category_of("HEAD~5"), CommitCategory::Bugfix);
+        assert_eq!(category_of("HEAD~4"), CommitCategory::Docs);
+        assert_eq!(category_of("HEAD~3"), CommitCategory::Feature);
+        assert_eq!(category_of("HEAD~2"), CommitCategory::Docs);
+        assert_eq!(category_of("HEAD~1"), CommitCategory::Refactor);
+        assert_eq!(category_of("HEAD"), CommitCategory::Other);
+        Ok(())
+    }
+
+    #[cfg(feature = "c-grammar")]
+    #[test]
+    fn changed_typedef_parameter_is_reported() -> Result<()> {
+        with_repo_containing_function_pointer_bug(|project_path| {
+            let prj_str = project_path.to_str().unwrap();
+            let some_repo = CodeRepository::new(prj_str).unwrap();
+            let commit = git2::Oid::from_str(&get_last_commit(prj_str)).unwrap();
+            let changes = some_repo.signature_param_changes(commit, Path::new("main.c")).unwrap();
+            assert_eq!(changes, vec![ParamChange{
+                function: "fpt".into(),
+                old_param: "unsigned int i".into(),
+                new_param: "int i".into(),
+            }]);
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn annotated_lines_carry_origin_and_numbers() -> Result<()> {
+        with_repo_containing_function_pointer_bug(|project_path| {
+            let prj_str = project_path.to_str().unwrap();
+            let some_repo = CodeRepository::new(prj_str).unwrap();
+            let commit = git2::Oid::from_str(&get_last_commit(prj_str)).unwrap();
+            let lines = some_repo.annotated_lines(commit).unwrap();
+
+            let deleted: Vec<&AnnotatedLine> = lines.iter().filter(|l| l.origin == '-').collect();
+            assert_eq!(deleted, vec![&AnnotatedLine{
+                path: PathBuf::from("main.c"),
+                origin: '-',
+                old_lineno: Some(7),
+                new_lineno: None,
+                content: "typedef void (*fpt)(unsigned int i);\n".into(),
+            }]);
+            let added: Vec<&AnnotatedLine> = lines.iter().filter(|l| l.origin == '+').collect();
+            assert_eq!(added, vec![&AnnotatedLine{
+                path: PathBuf::from("main.c"),
+                origin: '+',
+                old_lineno: None,
+                new_lineno: Some(7),
+                content: "typedef void (*fpt)(int i);\n".into(),
+            }]);
+            assert!(lines.iter().any(|l| l.origin == ' ' && l.old_lineno.is_some() && l.new_lineno.is_some()));
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn changes_of_unknown_commit_are_an_error() -> Result<()> {
+        with_repo_containing_function_pointer_bug(|project_path| {
+            let some_repo = CodeRepository::new(project_path.to_str().unwrap()).unwrap();
+            let unknown = git2::Oid::from_str("0123456789abcdef0123456789abcdef01234567").unwrap();
+            assert!(some_repo.get_changes(unknown).is_err());
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn commits_have_to_match_all_patterns() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        commit_file(repo_dir.path(), "main.c", "int a;\n", "security hardening")?;
+        commit_file(repo_dir.path(), "main.c", "int b;\n", "security: fix CVE-2021-1234")?;
+        commit_file(repo_dir.path(), "main.c", "int c;\n", "bump version")?;
+
+        let some_repo = CodeRepository::new(repo_dir.path().to_str().unwrap())?;
+        let patterns = vec![Regex::new("security").unwrap(), Regex::new("CVE").unwrap()];
+        assert_eq!(some_repo.commits_matching(&patterns)?.len(), 2);
+        let matching_all = some_repo.commits_matching_all(&patterns)?;
+        assert_eq!(matching_all.len(), 1);
+        assert_eq!(matching_all[0].to_string(), git(repo_dir.path(), &["rev-parse", "HEAD~1"]));
+        Ok(())
+    }
+
+    #[test]
+    fn empty_patterns_match_all_commits_under_all_semantics() -> Result<()> {
+        with_repo_containing_linear_history(|project_path| {
+            let some_repo = CodeRepository::new(project_path.to_str().unwrap()).unwrap();
+            assert_eq!(some_repo.commits_matching_all(&[]).unwrap().len(), 3);
+            assert!(some_repo.commits_matching(&[]).unwrap().is_empty());
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn excluded_patterns_drop_commits() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        commit_file(repo_dir.path(), "main.c", "int a;\n", "fix bug in parser")?;
+        commit_file(repo_dir.path(), "main.c", "int b;\n", "fix typo bug")?;
+        commit_file(repo_dir.path(), "main.c", "int c;\n", "Revert \"fix bug in parser\"")?;
+
+        let some_repo = CodeRepository::new(repo_dir.path().to_str().unwrap())?;
+        let include = vec![Regex::new("bug").unwrap()];
+        let exclude = vec![Regex::new("typo").unwrap(), Regex::new("(?i)revert").unwrap()];
+        let filtered = some_repo.commits_matching_filtered(&include, &exclude)?;
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].to_string(), git(repo_dir.path(), &["rev-parse", "HEAD~2"]));
+        assert_eq!(some_repo.commits_matching_filtered(&include, &[])?, some_repo.commits_matching(&include)?);
+        Ok(())
+    }
+
+    #[test]
+    fn commits_are_walked_from_given_revision() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_file(path, "main.c", "int a;\n", "initial commit")?;
+        git(path, &["checkout", "-q", "-b", "release-2.x"]);
+        commit_file(path, "main.c", "int b;\n", "fix release bug")?;
+        git(path, &["checkout", "-q", "master"]);
+
+        let some_repo = CodeRepository::new(path.to_str().unwrap())?;
+        let patterns = vec![Regex::new("fix").unwrap()];
+        assert!(some_repo.commits_matching(&patterns)?.is_empty());
+        assert_eq!(some_repo.commits_matching_from("release-2.x", &patterns)?.len(), 1);
+        assert!(some_repo.commits_matching_from("no-such-branch", &patterns).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn broken_head_is_an_error_not_an_empty_result() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        std::fs::write(repo_dir.path().join(".git").join("HEAD"), "0123456789abcdef0123456789abcdef01234567\n")?;
+
+        let some_repo = CodeRepository::new(repo_dir.path().to_str().unwrap())?;
+        let patterns = vec![Regex::new(".*").unwrap()];
+        assert!(some_repo.commits_matching(&patterns).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn extract_added_or_both_sides() -> Result<()> {
+        with_repo_containing_function_pointer_bug(|project_path| {
+            let prj_str = project_path.to_str().unwrap();
+            let some_repo = CodeRepository::new(prj_str).unwrap();
             let commit = git2::Oid::from_str(&get_last_commit(prj_str)).unwrap();
-            let changes = some_repo.get_change_sets(commit);
-            let first_change = changes.first().unwrap();
-            let expected_line: usize = 6;
-            dbg!(first_change.ranges());
-            assert!(first_change.ranges().iter().find(|r| r.contains(&expected_line)).is_some());
-            assert!(first_change.text_ranges().concat().contains("typedef"));
-                
+
+            let added = some_repo.get_changes_with(commit, ExtractOptions::new().line_kind(LineKind::Added)).unwrap();
+            assert_eq!(added, "typedef void (*fpt)(int i);\n");
+            let both = some_repo.get_changes_with(commit, ExtractOptions::new().line_kind(LineKind::Both)).unwrap();
+            assert_eq!(both, "-typedef void (*fpt)(unsigned int i);\n+typedef void (*fpt)(int i);\n");
+            let deleted = some_repo.get_changes_with(commit, ExtractOptions::new().line_kind(LineKind::Deleted)).unwrap();
+            assert_eq!(deleted, some_repo.get_changes(commit).unwrap());
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn merge_commit_is_diffed_against_each_parent() -> Result<()> {
+        with_repo_containing_merge(|project_path| {
+            let some_repo = CodeRepository::new(project_path.to_str().unwrap()).unwrap();
+            let merge = git2::Oid::from_str(&git(project_path, &["rev-parse", "HEAD"])).unwrap();
+            let per_parent = some_repo.get_changes_per_parent(merge).unwrap();
+            assert_eq!(per_parent, vec!["int x;\n".to_string(), "int a;\n".to_string()]);
+            assert_eq!(some_repo.get_changes(merge).unwrap(), per_parent[0]);
+
+            let root = git2::Oid::from_str(&git(project_path, &["rev-list", "--max-parents=0", "HEAD"])).unwrap();
+            assert_eq!(some_repo.get_changes_per_parent(root).unwrap(), vec![String::new()]);
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn merge_commit_has_two_parents() -> Result<()> {
+        with_repo_containing_merge(|project_path| {
+            let some_repo = CodeRepository::new(project_path.to_str().unwrap()).unwrap();
+            let merge = git2::Oid::from_str(&git(project_path, &["rev-parse", "HEAD"])).unwrap();
+            assert_eq!(some_repo.parent_count(merge).unwrap(), 2);
+            assert!(some_repo.is_merge(merge).unwrap());
+
+            let root = git2::Oid::from_str(&git(project_path, &["rev-list", "--max-parents=0", "HEAD"])).unwrap();
+            assert_eq!(some_repo.parent_count(root).unwrap(), 0);
+            assert!(!some_repo.is_merge(root).unwrap());
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn commit_info_has_author_time_and_message() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        std::fs::write(path.join("main.c"), "int a;\n")?;
+        git(path, &["add", "main.c"]);
+        git(path, &["commit", "-q", "--author=Jane Doe <jane@example.com>", "-m", "fix crash\n\nDetails here."]);
+
+        let some_repo = CodeRepository::new(path.to_str().unwrap())?;
+        let oid = git2::Oid::from_str(&git(path, &["rev-parse", "HEAD"]))?;
+        let info = some_repo.commit_info(oid)?;
+        assert_eq!(info.author, "Jane Doe");
+        assert_eq!(info.email, "jane@example.com");
+        assert_eq!(info.time.to_string(), git(path, &["log", "-1", "--format=%ct"]));
+        assert_eq!(info.summary, "fix crash");
+        assert_eq!(info.message, "fix crash\n\nDetails here.\n");
+        Ok(())
+    }
+
+    #[test]
+    fn commits_are_filtered_by_author() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        for (author, content) in [("Jane Doe <jane@example.com>", "int a;\n"), ("John Roe <john@example.org>", "int b;\n")] {
+            std::fs::write(path.join("main.c"), content)?;
+            git(path, &["add", "main.c"]);
+            git(path, &["commit", "-q", "--author", author, "-m", "hotfix"]);
+        }
+
+        let some_repo = CodeRepository::new(path.to_str().unwrap())?;
+        let patterns = vec![Regex::new("hotfix").unwrap()];
+        let by_jane = some_repo.commits_matching_by_author(&patterns, &Regex::new("^Jane ").unwrap())?;
+        assert_eq!(by_jane.len(), 1);
+        assert_eq!(by_jane[0].to_string(), git(path, &["rev-parse", "HEAD~1"]));
+        let by_domain = some_repo.commits_matching_by_author(&patterns, &Regex::new(r"@example\.org>$").unwrap())?;
+        assert_eq!(by_domain.len(), 1);
+        assert_eq!(by_domain[0].to_string(), git(path, &["rev-parse", "HEAD"]));
+        Ok(())
+    }
+
+    #[test]
+    fn commits_are_filtered_by_date_range() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_file_at(path, "main.c", "int a;\n", "fix early", 1_600_000_000)?;
+        commit_file_at(path, "main.c", "int b;\n", "fix middle", 1_610_000_000)?;
+        commit_file_at(path, "main.c", "int c;\n", "fix late", 1_620_000_000)?;
+
+        let some_repo = CodeRepository::new(path.to_str().unwrap())?;
+        let patterns = vec![Regex::new("fix").unwrap()];
+        let between = some_repo.commits_matching_between(&patterns, 1_605_000_000, 1_615_000_000)?;
+        assert_eq!(between.len(), 1);
+        assert_eq!(between[0].to_string(), git(path, &["rev-parse", "HEAD~1"]));
+
+        let inclusive = some_repo.commits_matching_between(&patterns, 1_610_000_000, 1_620_000_000)?;
+        assert_eq!(inclusive.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn keyword_only_in_body_does_not_match_subject() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_files(path, &[("main.c", "int a;\n")], "update changelog\n\n- fix crash on exit")?;
+        commit_files(path, &[("main.c", "int b;\n")], "fix overflow")?;
+
+        let some_repo = CodeRepository::new(path.to_str().unwrap())?;
+        let patterns = vec![Regex::new("fix").unwrap()];
+        let head = some_repo.repo.head()?.target().unwrap();
+        let changelog = some_repo.repo.find_commit(head)?.parent_id(0)?;
+        assert!(some_repo.contains_pattern(changelog, &patterns)?);
+        assert!(!some_repo.contains_pattern_subject(changelog, &patterns)?);
+        assert_eq!(some_repo.commits_matching_subject(&patterns)?, vec![head]);
+        Ok(())
+    }
+
+    #[test]
+    fn combined_patterns_match_like_single_patterns() -> Result<()> {
+        with_repo_containing_linear_history(|repo_path| {
+            let some_repo = CodeRepository::new(repo_path.to_str().unwrap()).unwrap();
+            let patterns: Vec<Regex> = ["second", "^fix third", "nothing", "f.rst"].iter()
+                .map(|p| Regex::new(p).unwrap())
+                .collect();
+            let expected = some_repo.commits_where(|oid| some_repo.contains_pattern(oid, &patterns)).unwrap();
+            assert_eq!(expected.len(), 3);
+            assert_eq!(some_repo.commits_matching(&patterns).unwrap(), expected);
+            assert!(some_repo.commits_matching(&[]).unwrap().is_empty());
+        })
+    }
+
+    #[test]
+    fn builder_flags_are_kept_when_matching() -> Result<()> {
+        with_repo_containing_linear_history(|repo_path| {
+            let some_repo = CodeRepository::new(repo_path.to_str().unwrap()).unwrap();
+            let patterns = [regex::RegexBuilder::new("FIX SECOND").case_insensitive(true).build().unwrap()];
+            assert_eq!(some_repo.commits_matching(&patterns).unwrap().len(), 1);
+            assert_eq!(some_repo.count_matching(&patterns).unwrap(), 1);
+            assert_eq!(some_repo.scan().patterns(&patterns).commits().unwrap().len(), 1);
+            assert_eq!(some_repo.commits_matching_filtered(&patterns, &[]).unwrap().len(), 1);
+        })
+    }
+
+    #[test]
+    fn regex_set_matches_like_its_patterns() -> Result<()> {
+        with_repo_containing_linear_history(|repo_path| {
+            let some_repo = CodeRepository::new(repo_path.to_str().unwrap()).unwrap();
+            let patterns: Vec<Regex> = ["second", "^fix third", "nothing"].iter()
+                .map(|p| Regex::new(p).unwrap())
+                .collect();
+            let set = RegexSet::new(["second", "^fix third", "nothing"]).unwrap();
+            assert_eq!(some_repo.commits_matching_set(&set).unwrap(), some_repo.commits_matching(&patterns).unwrap());
+            assert_eq!(some_repo.commits_matching_set(&set).unwrap().len(), 2);
+
+            let ignoring_case = regex::RegexSetBuilder::new(["FIX SECOND"]).case_insensitive(true).build().unwrap();
+            assert_eq!(some_repo.scan().pattern_set(&ignoring_case).count().unwrap(), 1);
+            assert!(some_repo.commits_matching_set(&RegexSet::empty()).unwrap().is_empty());
+        })
+    }
+
+    #[test]
+    fn match_spans_locate_keywords_in_message() -> Result<()> {
+        with_repo_containing_linear_history(|repo_path| {
+            let some_repo = CodeRepository::new(repo_path).unwrap();
+            let head = some_repo.repo.head().unwrap().target().unwrap();
+            let patterns = vec![Regex::new("i").unwrap(), Regex::new("third").unwrap()];
+            assert_eq!(some_repo.match_spans(head, &patterns).unwrap(), vec![1..2, 6..7, 4..9]);
+            assert!(some_repo.match_spans(head, &[Regex::new("bug").unwrap()]).unwrap().is_empty());
+        })
+    }
+
+    #[test]
+    fn plain_terms_match_literally_ignoring_case() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_file(path, "main.c", "int a;\n", "fixed a BUG")?;
+        commit_file(path, "main.cpp", "int b;\n", "Port to C++")?;
+        commit_file(path, "main.c", "int c;\n", "Port to C")?;
+
+        let some_repo = CodeRepository::new(path)?;
+        assert_eq!(some_repo.commits_matching_ci(&["Bug"])?.len(), 1);
+        let ported: Vec<String> = some_repo.commits_matching_ci(&["c++"])?.into_iter()
+            .map(|oid| some_repo.commit_info(oid).unwrap().summary)
+            .collect();
+        assert_eq!(ported, vec!["Port to C++"]);
+        Ok(())
+    }
+
+    #[test]
+    fn commits_are_found_by_changed_code() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_file(path, "main.c", "void f(char *d) {\n}\n", "initial commit")?;
+        commit_file(path, "main.c", "void f(char *d) {\n    strcpy(d, s);\n}\n", "cleanup")?;
+        commit_file(path, "main.c", "void f(char *d) {\n    strncpy(d, s, n);\n}\n", "more cleanup")?;
+
+        let some_repo = CodeRepository::new(path)?;
+        let summaries = |line_kind| -> Vec<String> {
+            some_repo.commits_changing(&[Regex::new(r"\bstrcpy\(").unwrap()], line_kind).unwrap().into_iter()
+                .map(|oid| some_repo.commit_info(oid).unwrap().summary)
+                .collect()
+        };
+        assert_eq!(summaries(LineKind::Added), vec!["cleanup"]);
+        assert_eq!(summaries(LineKind::Deleted), vec!["more cleanup"]);
+        assert_eq!(summaries(LineKind::Both), vec!["more cleanup", "cleanup"]);
+        Ok(())
+    }
+
+    #[test]
+    fn scan_combines_filters() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_file_at(path, "main.c", "int a;\n", "fix main", 1_000)?;
+        commit_file_at(path, "other.c", "int b;\n", "fix other", 2_000)?;
+        commit_file_at(path, "main.c", "int c;\n", "fix main again", 3_000)?;
+        commit_file_at(path, "main.c", "int d;\n", "tweak main", 4_000)?;
+
+        let some_repo = CodeRepository::new(path)?;
+        let summaries = |oids: Vec<Oid>| -> Vec<String> {
+            oids.into_iter().map(|oid| some_repo.commit_info(oid).unwrap().summary).collect()
+        };
+        let patterns = vec![Regex::new("fix").unwrap()];
+        assert_eq!(summaries(some_repo.scan().patterns(&patterns).paths(&["main.c"]).commits()?), vec!["fix main again", "fix main"]);
+        assert_eq!(summaries(some_repo.scan().patterns(&patterns).since(2_000).commits()?), vec!["fix main again", "fix other"]);
+        assert_eq!(summaries(some_repo.scan().paths(&["*.c"]).until(3_000).limit(1).commits()?), vec!["fix main again"]);
+        assert!(some_repo.scan().patterns(&[]).commits()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn scan_reports_missing_commits() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_file(path, "main.c", "int a;\n", "fix main")?;
+        commit_file(path, "main.c", "int b;\n", "fix main again")?;
+        remove_object(path, "HEAD~1")?;
+
+        let some_repo = CodeRepository::new(path)?;
+        assert!(some_repo.scan().commits().is_err());
+        assert!(some_repo.scan().count().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn labeled_commits_carry_indices_of_matching_patterns() -> Result<()> {
+        with_repo_containing_linear_history(|repo_path| {
+            let some_repo = CodeRepository::new(repo_path.to_str().unwrap()).unwrap();
+            let patterns: Vec<Regex> = ["second", "nothing", "fix"].iter()
+                .map(|p| Regex::new(p).unwrap())
+                .collect();
+            let labeled: Vec<(String, Vec<usize>)> = some_repo.commits_matching_labeled(&patterns).unwrap().into_iter()
+                .map(|(oid, matched)| (some_repo.commit_info(oid).unwrap().summary, matched))
+                .collect();
+            assert_eq!(labeled, vec![
+                ("fix third".to_string(), vec![2]),
+                ("fix second".to_string(), vec![0, 2]),
+                ("fix first".to_string(), vec![2]),
+            ]);
+            assert!(some_repo.commits_matching_labeled(&patterns[1..2]).unwrap().is_empty());
+
+            let set = RegexSet::new(["second", "nothing", "fix"]).unwrap();
+            assert_eq!(some_repo.commits_matching_labeled_set(&set).unwrap(), some_repo.commits_matching_labeled(&patterns).unwrap());
+        })
+    }
+
+    #[test]
+    fn labeling_reports_missing_commits() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_file(path, "main.c", "int a;\n", "fix main")?;
+        commit_file(path, "main.c", "int b;\n", "fix main again")?;
+        remove_object(path, "HEAD~1")?;
+
+        let some_repo = CodeRepository::new(path)?;
+        assert!(some_repo.commits_matching_labeled(&[Regex::new("fix")?]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn matching_reports_missing_commits() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_file(path, "main.c", "int a;\n", "fix main")?;
+        commit_file(path, "main.c", "int b;\n", "fix main again")?;
+        remove_object(path, "HEAD~1")?;
+
+        let some_repo = CodeRepository::new(path)?;
+        let patterns = vec![Regex::new("fix")?];
+        assert!(some_repo.commits_matching_all(&patterns).is_err());
+        assert!(some_repo.commits_matching_subject(&patterns).is_err());
+        assert!(some_repo.commits_matching_from("HEAD", &patterns).is_err());
+        assert!(some_repo.commits_matching_first_parent(&patterns).is_err());
+        assert!(some_repo.commits_matching_by_author(&patterns, &Regex::new(".")?).is_err());
+        assert!(some_repo.commits_changing(&patterns, LineKind::Both).is_err());
+        assert!(some_repo.commits_matching_iter(&patterns).any(|oid| oid.is_err()));
+        #[cfg(feature = "rayon")]
+        assert!(some_repo.commits_matching_par(&patterns).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn matching_commits_are_paired_with_changes() -> Result<()> {
+        with_repo_containing_linear_history(|repo_path| {
+            let some_repo = CodeRepository::new(repo_path).unwrap();
+            let patterns = vec![Regex::new("second|third").unwrap()];
+            let changes: Vec<String> = some_repo.matching_changes(&patterns)
+                .map(|item| item.unwrap().1)
+                .collect();
+            assert_eq!(changes, vec!["int b;\n", "int a;\n"]);
+            let oids: Vec<Oid> = some_repo.matching_changes(&patterns).map(|item| item.unwrap().0).collect();
+            assert_eq!(oids, some_repo.commits_matching(&patterns).unwrap());
+        })
+    }
+
+    #[test]
+    fn matching_commits_are_iterated_lazily() -> Result<()> {
+        with_repo_containing_linear_history(|repo_path| {
+            let some_repo = CodeRepository::new(repo_path.to_str().unwrap()).unwrap();
+            let patterns = vec![Regex::new("fix").unwrap()];
+            let all: Vec<Oid> = some_repo.commits_matching_iter(&patterns).collect::<Result<_, _>>().unwrap();
+            assert_eq!(all, some_repo.commits_matching(&patterns).unwrap());
+
+            let newest = some_repo.commits_matching_iter(&patterns).next().unwrap().unwrap();
+            assert_eq!(newest, all[0]);
+        })
+    }
+
+    #[test]
+    fn iterating_an_empty_repository_yields_nothing() -> Result<()> {
+        with_empty_repo(|repo_path| {
+            let some_repo = CodeRepository::new(repo_path.to_str().unwrap()).unwrap();
+            assert_eq!(some_repo.commits_matching_iter(&[Regex::new("fix").unwrap()]).count(), 0);
+        })
+    }
+
+    #[test]
+    fn repository_can_be_opened_from_a_path() -> Result<()> {
+        with_repo_containing_linear_history(|repo_path| {
+            let some_repo = CodeRepository::new(repo_path).unwrap();
+            assert_eq!(some_repo.commits_matching(&[Regex::new("fix").unwrap()]).unwrap().len(), 3);
+        })
+    }
+
+    #[test]
+    #[cfg(feature = "c-grammar")]
+    fn changed_functions_of_commit() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        let original = indoc!{"
+        int g;
+
+        void first() {
+            g = 1;
+            g = 2;
+        }
+
+        void second() {
+            g = 3;
+        }
+        "};
+        commit_files(path, &[("main.c", original), ("notes.txt", "todo\n")], "initial commit")?;
+        commit_files(path, &[
+            ("main.c", &original.replace("    g = 2;\n", "").replace("g = 3", "g = 4")),
+            ("notes.txt", "done\n"),
+        ], "fix functions")?;
+
+        let some_repo = CodeRepository::new(path)?;
+        let head = some_repo.repo.head()?.target().unwrap();
+        assert_eq!(some_repo.changed_functions(head)?, vec![(PathBuf::from("main.c"), vec![
+            "void first() {\n    g = 1;\n}".to_string(),
+            "void second() {\n    g = 4;\n}".to_string(),
+        ])]);
+        Ok(())
+    }
+
+    #[test]
+    fn old_blob_is_read_from_parent() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_file(path, "main.c", "int a;\n", "initial commit")?;
+        commit_files(path, &[("main.c", "int b;\n"), ("new.c", "int c;\n")], "fix and add")?;
+
+        let some_repo = CodeRepository::new(path)?;
+        let head = some_repo.repo.head()?.target().unwrap();
+        assert_eq!(some_repo.old_blob(head, Path::new("main.c"))?, "int a;\n");
+        assert!(matches!(some_repo.old_blob(head, Path::new("new.c")), Err(CodeRepositoryError::NotFound(_))));
+        let initial = some_repo.repo.find_commit(head)?.parent_id(0)?;
+        assert!(matches!(some_repo.old_blob(initial, Path::new("main.c")), Err(CodeRepositoryError::NotFound(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn changes_are_attributed_to_their_file() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_files(path, &[("main.c", "int a;\nint b;\n"), ("util.c", "int c;\n")], "initial commit")?;
+        commit_files(path, &[("main.c", "int b;\n"), ("util.c", "int d;\n")], "fix both")?;
+
+        let some_repo = CodeRepository::new(path)?;
+        let head = some_repo.repo.head()?.target().unwrap();
+        let by_file = some_repo.get_changes_by_file(head)?;
+        assert_eq!(by_file.len(), 2);
+        assert_eq!(by_file[Path::new("main.c")], "int a;\n");
+        assert_eq!(by_file[Path::new("util.c")], "int c;\n");
+        Ok(())
+    }
+
+    #[test]
+    fn changes_are_restricted_to_pathspec() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        std::fs::create_dir(path.join("src"))?;
+        commit_files(path, &[("src/main.c", "int a;\n"), ("gen.c", "int b;\n")], "initial commit")?;
+        commit_files(path, &[("src/main.c", "int c;\n"), ("gen.c", "int d;\n")], "fix both")?;
+
+        let some_repo = CodeRepository::new(path)?;
+        let head = some_repo.repo.head()?.target().unwrap();
+        let mut options = ExtractOptions::new();
+        options.pathspec("src/*.c");
+        assert_eq!(some_repo.get_changes_with(head, &options)?, "int a;\n");
+
+        let change_sets = some_repo.get_change_sets_with(head, &options)?;
+        assert_eq!(change_sets.len(), 1);
+        assert_eq!(change_sets[0].filename, Path::new("src/main.c"));
+        Ok(())
+    }
+
+    #[test]
+    fn reindentation_is_ignored_with_whitespace() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_file(path, "main.c", "void f() {\nreturn;\n}\n", "initial commit")?;
+        commit_file(path, "main.c", "void f() {\n    return;  \n}\n", "reindent")?;
+
+        let some_repo = CodeRepository::new(path)?;
+        let head = some_repo.repo.head()?.target().unwrap();
+        assert_eq!(some_repo.get_changes(head)?, "return;\n");
+        let mut options = ExtractOptions::new();
+        options.ignore_whitespace(true);
+        assert_eq!(some_repo.get_changes_with(head, &options)?, "");
+        assert!(some_repo.get_change_sets_with(head, &options)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn renamed_file_only_yields_edited_lines() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_file(path, "old.c", "int a;\nint b;\nint c;\nint d;\nint e;\n", "initial commit")?;
+        git(path, &["mv", "old.c", "new.c"]);
+        commit_file(path, "new.c", "int a;\nint b;\nint x;\nint d;\nint e;\n", "rename and fix")?;
+
+        let some_repo = CodeRepository::new(path)?;
+        let head = some_repo.repo.head()?.target().unwrap();
+        assert_eq!(some_repo.get_changes(head)?, "int a;\nint b;\nint c;\nint d;\nint e;\n");
+        let mut options = ExtractOptions::new();
+        options.detect_renames(true);
+        assert_eq!(some_repo.get_changes_with(head, &options)?, "int c;\n");
+        let change_sets = some_repo.get_change_sets_with(head, &options)?;
+        assert_eq!(change_sets.len(), 1);
+        assert_eq!(change_sets[0].filename, Path::new("new.c"));
+        assert_eq!(change_sets[0].status, ChangeStatus::Renamed{old_path: PathBuf::from("old.c")});
+        assert_eq!(change_sets[0].text_ranges(), vec!["int c;"]);
+        assert_eq!(change_sets[0].lines, vec![2]);
+        Ok(())
+    }
+
+    #[test]
+    fn added_file_yields_its_added_lines() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_file(path, "main.c", "int a;\n", "initial commit")?;
+        commit_files(path, &[("main.c", "int b;\n"), ("new.c", "int x;\nint y;\n")], "add new")?;
+
+        let some_repo = CodeRepository::new(path)?;
+        let head = some_repo.repo.head()?.target().unwrap();
+        let mut change_sets = some_repo.get_change_sets(head)?;
+        change_sets.sort_by(|a, b| a.filename.cmp(&b.filename));
+        assert_eq!(change_sets.len(), 2);
+        assert_eq!(change_sets[0].filename, Path::new("main.c"));
+        assert_eq!(change_sets[0].status, ChangeStatus::Modified);
+        assert_eq!(change_sets[0].text_ranges(), vec!["int a;"]);
+        assert_eq!(change_sets[1].filename, Path::new("new.c"));
+        assert_eq!(change_sets[1].status, ChangeStatus::Added);
+        assert_eq!(change_sets[1].text_ranges(), vec!["int x;\nint y;"]);
+        Ok(())
+    }
+
+    #[test]
+    fn binary_files_are_skipped() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_files(path, &[("main.c", "int a;\n"), ("image.bin", "head\0er\nbinary payload\n")], "initial commit")?;
+        commit_files(path, &[("main.c", "int b;\n"), ("image.bin", "head\0er\nother payload\n")], "fix both")?;
+
+        let some_repo = CodeRepository::new(path)?;
+        let head = some_repo.repo.head()?.target().unwrap();
+        assert_eq!(some_repo.get_changes(head)?, "int a;\n");
+        let change_sets = some_repo.get_change_sets(head)?;
+        assert_eq!(change_sets.len(), 1);
+        assert_eq!(change_sets[0].filename, Path::new("main.c"));
+
+        let mut options = ExtractOptions::new();
+        options.include_binary(true);
+        assert!(some_repo.get_changes_with(head, &options)?.contains("binary payload"));
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_utf8_message_is_matched_lossily() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_file(path, "main.c", "int a;\n", "initial commit")?;
+        std::fs::write(path.join("main.c"), "int b;\n")?;
+        let message_file = path.join(".git").join("LATIN1_MSG");
+        std::fs::write(&message_file, b"fix bug in \xe4\xf6\xfc handling\n")?;
+        git(path, &["commit", "-q", "-a", "-F", message_file.to_str().unwrap()]);
+
+        let some_repo = CodeRepository::new(path)?;
+        let patterns = vec![Regex::new("fix bug").unwrap()];
+        let matching = some_repo.commits_matching(&patterns)?;
+        assert_eq!(matching.len(), 1);
+        assert!(some_repo.contains_pattern(matching[0], &patterns)?);
+        assert!(some_repo.contains_pattern_subject(matching[0], &patterns)?);
+        Ok(())
+    }
+
+    #[test]
+    fn commit_stats_count_files_and_lines() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_files(path, &[("a.c", "int a;\nint b;\n"), ("b.c", "int c;\n")], "initial commit")?;
+        commit_files(path, &[("a.c", "int x;\n"), ("b.c", "int c;\nint d;\n")], "fix things")?;
+
+        let some_repo = CodeRepository::new(path)?;
+        let head = some_repo.repo.head()?.target().unwrap();
+        assert_eq!(some_repo.commit_stats(head)?, DiffStats{files_changed: 2, insertions: 2, deletions: 2});
+        Ok(())
+    }
+
+    #[test]
+    fn diff_lines_are_streamed_until_callback_stops() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_file(path, "main.c", "int a;\nint b;\n", "initial commit")?;
+        commit_file(path, "main.c", "int a;\nint x;\n", "fix b")?;
+
+        let some_repo = CodeRepository::new(path)?;
+        let head = some_repo.repo.head()?.target().unwrap();
+        let mut lines = vec![];
+        some_repo.get_changes_for_each(head, |file, kind, content| {
+            lines.push((file.to_path_buf(), kind, content.to_string()));
+            true
+        })?;
+        assert_eq!(lines, vec![
+            (PathBuf::from("main.c"), DiffLineType::Context, "int a;\n".to_string()),
+            (PathBuf::from("main.c"), DiffLineType::Deletion, "int b;\n".to_string()),
+            (PathBuf::from("main.c"), DiffLineType::Addition, "int x;\n".to_string()),
+        ]);
+
+        let mut visited = 0;
+        some_repo.get_changes_for_each(head, |_, _, _| {
+            visited += 1;
+            false
+        })?;
+        assert_eq!(visited, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn streamed_lines_follow_the_options() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_files(path, &[("main.c", "int a;\n"), ("other.c", "int b;\n")], "initial commit")?;
+        commit_files(path, &[("main.c", "int x;\n"), ("other.c", "int y;\n")], "fix both")?;
+
+        let some_repo = CodeRepository::new(path)?;
+        let head = some_repo.repo.head()?.target().unwrap();
+        let mut files = HashSet::new();
+        some_repo.get_changes_for_each_with(head, ExtractOptions::new().pathspec("other.c"), |file, _, _| {
+            files.insert(file.to_path_buf());
+            true
         })?;
+        assert_eq!(files, HashSet::from([PathBuf::from("other.c")]));
+        Ok(())
+    }
+
+    #[test]
+    fn context_lines_are_extracted_in_diff_order() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_file(path, "main.c", "int a;\nint b;\nint c;\n", "initial commit")?;
+        commit_file(path, "main.c", "int a;\nint x;\nint c;\n", "fix b")?;
+
+        let some_repo = CodeRepository::new(path)?;
+        let head = some_repo.repo.head()?.target().unwrap();
+        let mut options = ExtractOptions::new();
+        options.include_context(true);
+        assert_eq!(some_repo.get_changes_with(head, &options)?, "int a;\nint b;\nint c;\n");
+        options.line_kind(LineKind::Both);
+        assert_eq!(some_repo.get_changes_with(head, &options)?, " int a;\n-int b;\n+int x;\n int c;\n");
+        assert_eq!(some_repo.get_changes(head)?, "int b;\n");
+        Ok(())
+    }
+
+    #[test]
+    fn nearby_hunks_are_merged_by_interhunk_lines() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        let original: String = (0..20).map(|n| format!("int v{};\n", n)).collect();
+        commit_file(path, "main.c", &original, "initial commit")?;
+        let edited = original.replace("int v5;", "int w5;").replace("int v12;", "int w12;");
+        commit_file(path, "main.c", &edited, "fix two lines")?;
+
+        let some_repo = CodeRepository::new(path)?;
+        let head = some_repo.repo.head()?.target().unwrap();
+        let mut options = ExtractOptions::new();
+        options.context_lines(1);
+        assert_eq!(some_repo.hunk_ranges(head, &options)?, vec![
+            (PathBuf::from("main.c"), 4..7),
+            (PathBuf::from("main.c"), 11..14),
+        ]);
+        options.interhunk_lines(4);
+        assert_eq!(some_repo.hunk_ranges(head, &options)?, vec![(PathBuf::from("main.c"), 4..14)]);
+        Ok(())
+    }
+
+    #[test]
+    fn first_parent_walk_skips_branch_commits() -> Result<()> {
+        with_repo_containing_merge(|repo_path| {
+            let some_repo = CodeRepository::new(repo_path).unwrap();
+            let patterns = vec![Regex::new("fix|merge").unwrap()];
+            let summaries: Vec<String> = some_repo.commits_matching_first_parent(&patterns).unwrap().into_iter()
+                .map(|oid| some_repo.commit_info(oid).unwrap().summary)
+                .collect();
+            assert_eq!(summaries, vec!["merge feature", "fix main on master"]);
+            assert_eq!(some_repo.commits_matching(&patterns).unwrap().len(), 3);
+        })
+    }
+
+    #[test]
+    fn commits_are_sorted_by_time() -> Result<()> {
+        let repo_dir = create_temporary_repository()?;
+        let path = repo_dir.path();
+        commit_file_at(path, "main.c", "int a;\n", "fix early", 1_600_000_000)?;
+        commit_file_at(path, "main.c", "int b;\n", "fix middle", 1_610_000_000)?;
+        commit_file_at(path, "main.c", "int c;\n", "fix late", 1_620_000_000)?;
+
+        let patterns = vec![Regex::new("fix").unwrap()];
+        let some_repo = CodeRepository::new(path)?.with_sorting(Sort::TIME);
+        let times: Vec<i64> = some_repo.commits_matching(&patterns)?.into_iter()
+            .map(|oid| some_repo.commit_info(oid).unwrap().time)
+            .collect();
+        assert_eq!(times, vec![1_620_000_000, 1_610_000_000, 1_600_000_000]);
+
+        let some_repo = some_repo.with_sorting(Sort::TIME | Sort::REVERSE);
+        let oldest = some_repo.commits_matching(&patterns)?[0];
+        assert_eq!(some_repo.commit_info(oldest)?.time, 1_600_000_000);
         Ok(())
     }
+
+    #[test]
+    fn commits_are_selected_between_revisions() -> Result<()> {
+        with_repo_containing_linear_history(|repo_path| {
+            let some_repo = CodeRepository::new(repo_path).unwrap();
+            let patterns = vec![Regex::new("fix").unwrap()];
+            let summaries: Vec<String> = some_repo.commits_matching_range("HEAD~2", "HEAD", &patterns).unwrap().into_iter()
+                .map(|oid| some_repo.commit_info(oid).unwrap().summary)
+                .collect();
+            assert_eq!(summaries, vec!["fix third", "fix second"]);
+            assert!(some_repo.commits_matching_range("no-such-tag", "HEAD", &patterns).is_err());
+        })
+    }
+
+    #[test]
+    fn matching_commits_are_limited() -> Result<()> {
+        with_repo_containing_linear_history(|repo_path| {
+            let some_repo = CodeRepository::new(repo_path).unwrap();
+            let patterns = vec![Regex::new("fix").unwrap()];
+            let all = some_repo.commits_matching(&patterns).unwrap();
+            assert_eq!(some_repo.commits_matching_limited(&patterns, 2).unwrap(), all[..2]);
+            assert_eq!(some_repo.commits_matching_limited(&patterns, 10).unwrap(), all);
+            assert!(some_repo.commits_matching_limited(&patterns, 0).unwrap().is_empty());
+        })
+    }
 }
@@ -0,0 +1,108 @@
+/// Treatment of the "\ No newline at end of file" markers in a diff.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EofNewline {
+    /// Leave the markers out of extracted changes.
+    #[default]
+    Skip,
+    /// Keep the markers belonging to the side of the diff being extracted.
+    Preserve,
+}
+
+/// Which side of a diff is extracted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineKind {
+    Added,
+    /// The code removed by a commit, which is what `get_changes` returns.
+    #[default]
+    Deleted,
+    /// Deleted and added lines in diff order, prefixed with `-` and `+` respectively.
+    Both,
+}
+
+/// Settings for extracting changes from a commit, see `CodeRepository::get_changes_with`.
+#[derive(Debug, Clone, Default)]
+pub struct ExtractOptions {
+    pub(crate) line_kind: LineKind,
+    pub(crate) eof_newline: EofNewline,
+    pub(crate) include_typechange: bool,
+    pub(crate) pathspecs: Vec<String>,
+    pub(crate) ignore_whitespace: bool,
+    pub(crate) detect_renames: bool,
+    pub(crate) include_binary: bool,
+    pub(crate) context_lines: Option<u32>,
+    pub(crate) interhunk_lines: Option<u32>,
+    pub(crate) include_context: bool,
+}
+
+impl ExtractOptions {
+    pub fn new() -> ExtractOptions {
+        ExtractOptions::default()
+    }
+
+    pub fn line_kind(&mut self, kind: LineKind) -> &mut ExtractOptions {
+        self.line_kind = kind;
+        self
+    }
+
+    pub fn eof_newline(&mut self, handling: EofNewline) -> &mut ExtractOptions {
+        self.eof_newline = handling;
+        self
+    }
+
+    /// Extract files whose type changed, e.g. from a regular file to a symlink, as if the old
+    /// file was deleted and the new one added. Off by default, since such a delta is not an
+    /// edit of the source.
+    pub fn include_typechange(&mut self, include: bool) -> &mut ExtractOptions {
+        self.include_typechange = include;
+        self
+    }
+
+    /// Only extract files matching `pathspec`, a path prefix or a glob like `src/*.c`. Files
+    /// matching any of several pathspecs are extracted.
+    pub fn pathspec(&mut self, pathspec: &str) -> &mut ExtractOptions {
+        self.pathspecs.push(pathspec.to_string());
+        self
+    }
+
+    /// Treat lines differing only in whitespace, including at the end of the line, as
+    /// unchanged, so reindented code is not extracted.
+    pub fn ignore_whitespace(&mut self, ignore: bool) -> &mut ExtractOptions {
+        self.ignore_whitespace = ignore;
+        self
+    }
+
+    /// Pair deleted and added files with similar content as renames, so only the lines edited
+    /// along with the rename are extracted instead of the whole old file. Off by default.
+    pub fn detect_renames(&mut self, detect: bool) -> &mut ExtractOptions {
+        self.detect_renames = detect;
+        self
+    }
+
+    /// Extract files git considers binary, e.g. because they contain null bytes, as text.
+    /// Off by default, since their content decodes to noise.
+    pub fn include_binary(&mut self, include: bool) -> &mut ExtractOptions {
+        self.include_binary = include;
+        self
+    }
+
+    /// Number of unchanged lines around each change that belong to its hunk, 3 by default.
+    pub fn context_lines(&mut self, lines: u32) -> &mut ExtractOptions {
+        self.context_lines = Some(lines);
+        self
+    }
+
+    /// Extract the unchanged lines of each hunk too, interleaved with the changed ones in diff
+    /// order, so the result reads like the hunk itself. Combined with `LineKind::Both` they are
+    /// prefixed with a space. Off by default.
+    pub fn include_context(&mut self, include: bool) -> &mut ExtractOptions {
+        self.include_context = include;
+        self
+    }
+
+    /// Maximum number of unchanged lines between two hunks for them to be merged into one,
+    /// 0 by default.
+    pub fn interhunk_lines(&mut self, lines: u32) -> &mut ExtractOptions {
+        self.interhunk_lines = Some(lines);
+        self
+    }
+}
@@ -0,0 +1,78 @@
+//! Heuristics recognizing generated files such as lockfiles and minified sources, whose
+//! changes are noise when looking for hand-written code.
+
+use std::path::Path;
+
+/// File names of lockfiles written by package managers.
+const GENERATED_FILE_NAMES: &[&str] = &[
+    "Cargo.lock", "package-lock.json", "yarn.lock", "pnpm-lock.yaml",
+    "Gemfile.lock", "poetry.lock", "composer.lock", "go.sum",
+];
+
+/// File name suffixes of minified or otherwise machine-written files.
+const GENERATED_SUFFIXES: &[&str] = &[".min.js", ".min.css", ".js.map", ".css.map"];
+
+/// Average line length above which content is considered minified.
+const MAX_AVERAGE_LINE_LENGTH: usize = 300;
+
+/// Content shorter than this is never judged by its whitespace ratio.
+const MIN_LENGTH_FOR_WHITESPACE_RATIO: usize = 1000;
+
+/// Share of whitespace characters below which content is considered minified.
+const MIN_WHITESPACE_RATIO: f64 = 0.03;
+
+pub fn has_generated_name<P: AsRef<Path> + ?Sized>(path: &P) -> bool {
+    match path.as_ref().file_name().and_then(|n| n.to_str()) {
+        Some(name) => GENERATED_FILE_NAMES.contains(&name)
+            || GENERATED_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)),
+        None => false,
+    }
+}
+
+pub fn looks_generated(content: &str) -> bool {
+    let line_count = content.lines().count();
+    if line_count == 0 {
+        return false;
+    }
+    if content.len() / line_count > MAX_AVERAGE_LINE_LENGTH {
+        return true;
+    }
+
+    let total = content.chars().count();
+    let whitespace = content.chars().filter(|c| c.is_whitespace()).count();
+    total >= MIN_LENGTH_FOR_WHITESPACE_RATIO && (whitespace as f64 / total as f64) < MIN_WHITESPACE_RATIO
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn minified_javascript_looks_generated() {
+        let statement = "var a=function(b,c){return b+c},d=a(1,2);if(d>2){console.log(d)}";
+        let minified = statement.repeat(50);
+        assert!(looks_generated(&minified));
+    }
+
+    #[test]
+    fn hand_written_code_does_not_look_generated() {
+        let code = indoc!{r#"
+        #include <stdio.h>
+
+        int main() {
+            printf("hello\n");
+            return 0;
+        }
+        "#};
+        assert!(!looks_generated(code));
+        assert!(!looks_generated(""));
+    }
+
+    #[test]
+    fn lockfiles_and_minified_names_are_generated() {
+        assert!(has_generated_name("Cargo.lock"));
+        assert!(has_generated_name("web/vendor/jquery.min.js"));
+        assert!(!has_generated_name("src/main.c"));
+    }
+}
@@ -0,0 +1,82 @@
+use std::path::Path;
+
+/// Source languages recognized by their file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Language {
+    C,
+    Cpp,
+    Python,
+    Rust,
+    Go,
+    Java,
+    JavaScript,
+}
+
+impl Language {
+    pub fn from_extension(extension: &str) -> Option<Language> {
+        match extension {
+            "c" | "h" => Some(Language::C),
+            "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => Some(Language::Cpp),
+            "py" => Some(Language::Python),
+            "rs" => Some(Language::Rust),
+            "go" => Some(Language::Go),
+            "java" => Some(Language::Java),
+            "js" => Some(Language::JavaScript),
+            _ => None,
+        }
+    }
+
+    pub fn from_path<P: AsRef<Path> + ?Sized>(path: &P) -> Option<Language> {
+        let extension = path.as_ref().extension()?.to_str()?;
+        Language::from_extension(extension)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::C => "c",
+            Language::Cpp => "cpp",
+            Language::Python => "python",
+            Language::Rust => "rust",
+            Language::Go => "go",
+            Language::Java => "java",
+            Language::JavaScript => "javascript",
+        }
+    }
+
+    /// The tree-sitter grammar of the language, if the crate links one. Which grammars are
+    /// linked depends on the `c-grammar` and `rust-grammar` features.
+    pub fn grammar(&self) -> Option<tree_sitter::Language> {
+        match self {
+            #[cfg(feature = "c-grammar")]
+            Language::C => Some(tree_sitter_c::language()),
+            #[cfg(feature = "rust-grammar")]
+            Language::Rust => Some(tree_sitter_rust::language()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn c_headers_and_sources_are_c() {
+        assert_eq!(Language::from_path("main.c"), Some(Language::C));
+        assert_eq!(Language::from_path("include/main.h"), Some(Language::C));
+    }
+
+    #[test]
+    fn unknown_extension_has_no_language() {
+        assert_eq!(Language::from_path("README.md"), None);
+        assert_eq!(Language::from_path("Makefile"), None);
+    }
+
+    #[cfg(all(feature = "c-grammar", feature = "rust-grammar"))]
+    #[test]
+    fn only_linked_grammars_are_available() {
+        assert_eq!(Language::C.grammar(), Some(tree_sitter_c::language()));
+        assert_eq!(Language::Rust.grammar(), Some(tree_sitter_rust::language()));
+        assert_eq!(Language::Python.grammar(), None);
+    }
+}
@@ -6,10 +6,29 @@
 //! let repository = CodeRepository::new(".").unwrap();
 //! let patterns = vec![Regex::new("bug").unwrap()];
 //! for oid in repository.commits_matching(&patterns).unwrap() {
-//!     repository.get_change_sets(oid);
+//!     repository.get_change_sets(oid).unwrap();
+//! }
+//! for (oid, changes) in repository.matching_changes(&patterns).flatten() {
+//!     println!("{}:\n{}", oid, changes);
 //! }
 //! ```
+//!
+//! # Features
+//!
+//! - `c-grammar` (default): links the tree-sitter C grammar, used by `CodeRegion::new` and
+//!   for `.c` and `.h` files.
+//! - `rust-grammar` (default): links the tree-sitter Rust grammar for `.rs` files.
+//! - `serde`: serialization of `ChangeSet`.
+//! - `rayon`: `CodeRepository::commits_matching_par`.
+//!
+//! Without the grammar features only the git and diff functionality is built; a `CodeRegion`
+//! can still be created from any grammar with `CodeRegion::with_language`.
 
+pub mod blob_cache;
 pub mod code_repository;
 pub mod code_region;
+pub mod extract_options;
+pub mod generated;
 pub mod change_set;
+pub mod language;
+pub mod scan;
@@ -0,0 +1,140 @@
+use git2::{Oid, Sort};
+use regex::{Regex, RegexSet};
+use crate::code_repository::{CodeRepository, CodeRepositoryError, PatternSet};
+
+/// Filters for walking the history of a `CodeRepository`, see `CodeRepository::scan`. Every
+/// filter that is set has to accept a commit for it to be returned.
+pub struct ScanBuilder<'a> {
+    repository: &'a CodeRepository,
+    patterns: Option<PatternSet>,
+    author: Option<Regex>,
+    since: Option<i64>,
+    until: Option<i64>,
+    pathspecs: Vec<String>,
+    first_parent: bool,
+    limit: Option<usize>,
+}
+
+impl<'a> ScanBuilder<'a> {
+    pub(crate) fn new(repository: &'a CodeRepository) -> ScanBuilder<'a> {
+        ScanBuilder{
+            repository,
+            patterns: None,
+            author: None,
+            since: None,
+            until: None,
+            pathspecs: vec![],
+            first_parent: false,
+            limit: None,
+        }
+    }
+
+    /// Only commits whose message matches any of `patterns`, as in `commits_matching`. An
+    /// empty slice matches no commit, while leaving this unset matches every commit.
+    pub fn patterns(&mut self, patterns: &[Regex]) -> &mut ScanBuilder<'a> {
+        self.patterns = Some(PatternSet::new(patterns));
+        self
+    }
+
+    /// Like `patterns` with the patterns already combined in a `RegexSet`, which searches each
+    /// message once for all of them.
+    pub fn pattern_set(&mut self, patterns: &RegexSet) -> &mut ScanBuilder<'a> {
+        self.patterns = Some(PatternSet::Set(patterns.clone()));
+        self
+    }
+
+    /// Only commits whose author matches as `"Name <email>"`.
+    pub fn author(&mut self, author: &Regex) -> &mut ScanBuilder<'a> {
+        self.author = Some(author.clone());
+        self
+    }
+
+    /// Only commits made at or after `time`, in seconds since the Unix epoch.
+    pub fn since(&mut self, time: i64) -> &mut ScanBuilder<'a> {
+        self.since = Some(time);
+        self
+    }
+
+    /// Only commits made at or before `time`, in seconds since the Unix epoch.
+    pub fn until(&mut self, time: i64) -> &mut ScanBuilder<'a> {
+        self.until = Some(time);
+        self
+    }
+
+    /// Only commits touching a file matching any of `pathspecs`, path prefixes or globs like
+    /// `src/*.c` as in `ExtractOptions::pathspec`.
+    pub fn paths(&mut self, pathspecs: &[&str]) -> &mut ScanBuilder<'a> {
+        self.pathspecs.extend(pathspecs.iter().map(|p| p.to_string()));
+        self
+    }
+
+    /// Follow only the first parent of merge commits, see `commits_matching_first_parent`.
+    pub fn first_parent(&mut self, first_parent: bool) -> &mut ScanBuilder<'a> {
+        self.first_parent = first_parent;
+        self
+    }
+
+    /// Stop the walk once `max` commits are found.
+    pub fn limit(&mut self, max: usize) -> &mut ScanBuilder<'a> {
+        self.limit = Some(max);
+        self
+    }
+
+    /// The accepted commits in the order of the repository's walk.
+    pub fn commits(&self) -> Result<Vec<Oid>, CodeRepositoryError> {
+        let mut commits = vec![];
+        self.for_each_accepted(|oid| commits.push(oid))?;
+        Ok(commits)
+    }
+
+    /// Number of accepted commits, without collecting them.
+    pub fn count(&self) -> Result<usize, CodeRepositoryError> {
+        let mut count = 0;
+        self.for_each_accepted(|_| count += 1)?;
+        Ok(count)
+    }
+
+    fn for_each_accepted<F>(&self, mut accept: F) -> Result<(), CodeRepositoryError>
+        where F: FnMut(Oid)
+    {
+        let limit = self.limit.unwrap_or(usize::MAX);
+        if limit == 0 {
+            return Ok(());
+        }
+        let mut walk = self.repository.revwalk()?;
+        if !self.repository.push_head(&mut walk)? {
+            return Ok(());
+        }
+        if self.first_parent {
+            walk.set_sorting(self.repository.sorting() | Sort::TOPOLOGICAL)?;
+            walk.simplify_first_parent()?;
+        }
+
+        let mut accepted_count = 0;
+        for oid in walk {
+            let oid = oid?;
+            let accepted = self.patterns.as_ref().map_or(Ok(true), |p| self.repository.contains_pattern_set(oid, p))?
+                && self.author.as_ref().map_or(Ok(true), |a| self.repository.author_matches(oid, a))?
+                && self.in_time_window(oid)?
+                && (self.pathspecs.is_empty() || self.repository.touches_paths(oid, &self.pathspecs)?);
+            if accepted {
+                accept(oid);
+                accepted_count += 1;
+                if accepted_count == limit {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the commit lies within `since` and `until`. The commit is only looked up if
+    /// either is set.
+    fn in_time_window(&self, oid: Oid) -> Result<bool, CodeRepositoryError> {
+        if self.since.is_none() && self.until.is_none() {
+            return Ok(true);
+        }
+        let time = self.repository.repo().find_commit(oid)?.time().seconds();
+        Ok(self.since.is_none_or(|since| since <= time) && self.until.is_none_or(|until| time <= until))
+    }
+}